@@ -0,0 +1,26 @@
+use std::time::Instant;
+
+use space_invaders::emulator::Emulator;
+
+const ROM:    &str = concat!(env!("CARGO_MANIFEST_DIR"), "/rom/space_invaders");
+const FRAMES: u64  = 6000;
+
+fn main() {
+    let mut emulator = Emulator::new();
+
+    emulator.load_rom(ROM, &[]).unwrap();
+
+    let start = Instant::now();
+
+    for _ in 0..FRAMES {
+        emulator.run_frame().unwrap();
+    }
+
+    let host_seconds     = start.elapsed().as_secs_f64();
+    let emulated_seconds = FRAMES as f64 / 60.0;
+    let speed            = emulated_seconds / host_seconds;
+
+    println!("{} frames in {:.3}s: {:.1}x real-time", FRAMES, host_seconds, speed);
+
+    assert!(speed >= 100.0, "emulator ran slower than 100x real-time");
+}
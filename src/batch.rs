@@ -0,0 +1,148 @@
+use std::error::Error;
+use std::thread;
+
+use crate::env::{Action, Env, EnvConfig};
+
+// Independent games stepped together on scoped threads. The observations
+// of all games share one buffer, game after game, and a game that ends is
+// reset straight away, so its slot then holds the first observation of
+// the next episode.
+pub struct BatchEnv {
+    envs:         Vec<Env>,
+    threads:      usize,
+    observations: Vec<u8>,
+    rewards:      Vec<i32>,
+    dones:        Vec<bool>,
+}
+
+impl BatchEnv {
+    // Boots one game and clones the others from it. Game n draws its
+    // sticky actions and no-op starts from seed + n.
+    pub fn new(rom: Vec<u8>, config: EnvConfig, count: usize) -> Result<BatchEnv, Box<dyn Error>> {
+        if count == 0 {
+            return Err("a batch needs at least one game".into());
+        }
+
+        let mut first = Env::new(rom, config)?;
+
+        first.reset()?;
+
+        let (width, height) = first.observation_shape();
+        let envs = (0..count as u64).map(|index| {
+            let mut env = first.clone();
+
+            env.seed(config.seed.wrapping_add(index));
+            env
+        }).collect();
+
+        Ok(BatchEnv {
+            envs,
+            threads:      thread::available_parallelism().map_or(1, usize::from),
+            observations: vec![0; count * width * height],
+            rewards:      vec![0; count],
+            dones:        vec![false; count],
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.envs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.envs.is_empty()
+    }
+
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn observation_shape(&self) -> (usize, usize) {
+        self.envs[0].observation_shape()
+    }
+
+    pub fn observations(&self) -> &[u8] {
+        &self.observations
+    }
+
+    pub fn rewards(&self) -> &[i32] {
+        &self.rewards
+    }
+
+    pub fn dones(&self) -> &[bool] {
+        &self.dones
+    }
+
+    pub fn envs(&self) -> &[Env] {
+        &self.envs
+    }
+
+    pub fn reset(&mut self) -> Result<&[u8], Box<dyn Error>> {
+        self.run(|env, _, observation, reward, done| {
+            env.reset()?;
+            env.observation_into(observation);
+            *reward = 0;
+            *done   = false;
+            Ok(())
+        })?;
+        Ok(&self.observations)
+    }
+
+    pub fn step(&mut self, actions: &[Action]) -> Result<(), Box<dyn Error>> {
+        if actions.len() != self.envs.len() {
+            return Err(format!("{} actions for {} games", actions.len(), self.envs.len()).into());
+        }
+
+        self.run(|env, index, observation, reward, done| {
+            (*reward, *done) = env.act(actions[index])?;
+
+            if *done {
+                env.reset()?;
+            }
+            env.observation_into(observation);
+            Ok(())
+        })
+    }
+
+    // Splits the games into one run of neighbouring games per thread.
+    fn run<F>(&mut self, work: F) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(&mut Env, usize, &mut [u8], &mut i32, &mut bool) -> Result<(), Box<dyn Error>> + Sync,
+    {
+        let (width, height) = self.observation_shape();
+        let size            = width * height;
+        let per_thread      = self.envs.len().div_ceil(self.threads);
+        let work            = &work;
+
+        let chunk = |first: usize, envs: &mut [Env], observations: &mut [u8], rewards: &mut [i32],
+                     dones: &mut [bool]| -> Result<(), String> {
+            for (offset, env) in envs.iter_mut().enumerate() {
+                work(env, first + offset, &mut observations[offset * size..(offset + 1) * size],
+                     &mut rewards[offset], &mut dones[offset])
+                    .map_err(|error| format!("game {}: {}", first + offset, error))?;
+            }
+            Ok(())
+        };
+
+        if per_thread == self.envs.len() {
+            return chunk(0, &mut self.envs, &mut self.observations, &mut self.rewards, &mut self.dones)
+                .map_err(Into::into);
+        }
+
+        thread::scope(|scope| {
+            let chunk   = &chunk;
+            let handles: Vec<_> = self.envs.chunks_mut(per_thread)
+                .zip(self.observations.chunks_mut(per_thread * size))
+                .zip(self.rewards.chunks_mut(per_thread))
+                .zip(self.dones.chunks_mut(per_thread))
+                .enumerate()
+                .map(|(index, (((envs, observations), rewards), dones))| {
+                    scope.spawn(move || chunk(index * per_thread, envs, observations, rewards, dones))
+                })
+                .collect();
+
+            handles.into_iter().try_for_each(|handle| {
+                handle.join().unwrap_or_else(|_| Err("a game thread panicked".to_string()))
+            })
+        }).map_err(Into::into)
+    }
+}
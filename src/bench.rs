@@ -0,0 +1,75 @@
+use std::error::Error;
+use std::time::Instant;
+
+use space_invaders::emulator::Emulator;
+use space_invaders::io_ports::Button;
+
+const DEFAULT_FRAMES: u64 = 36000;
+
+// The headless emulator is expected to run gameplay at least this many
+// times faster than the cabinet.
+const TARGET_SPEED: f64 = 100.0;
+
+// Insert a coin and start a game every minute, firing and sweeping left
+// and right in between, so that the benchmark exercises actual gameplay
+// rather than the attract mode.
+fn script(emulator: &mut Emulator, frame: u64) {
+    let minute = frame % 3600;
+
+    emulator.set_button(Button::Coin,    (60..65).contains(&minute));
+    emulator.set_button(Button::P1Start, (120..125).contains(&minute));
+    emulator.set_button(Button::P1Fire,  frame % 20 < 2);
+    emulator.set_button(Button::P1Left,  frame % 180 < 90);
+    emulator.set_button(Button::P1Right, frame % 180 >= 90);
+}
+
+pub fn run(emulator: &mut Emulator, frames: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let frames = match frames {
+        Some(frames) => frames.parse()?,
+        None         => DEFAULT_FRAMES,
+    };
+
+    let start = Instant::now();
+
+    for frame in 0..frames {
+        script(emulator, frame);
+
+        emulator.run_frame()?;
+    }
+
+    let host_seconds = start.elapsed().as_secs_f64();
+    let statistics   = emulator.statistics();
+    let frames       = emulator.video().frames();
+    let emulated     = emulator.cycles() as f64 / emulator.timing().clock_hz() as f64;
+    let speed        = emulated / host_seconds;
+
+    println!("frames:          {}", frames);
+    println!("host time:       {:.3} s", host_seconds);
+    println!("emulated time:   {:.3} s", emulated);
+    println!("speed:           {:.1}x real-time", speed);
+    println!("frames/s:        {:.1}", frames as f64 / host_seconds);
+    println!("effective clock: {:.2} MHz", emulator.cycles() as f64 / host_seconds / 1e6);
+    println!("instructions/s:  {:.0}", statistics.instructions as f64 / host_seconds);
+    println!();
+    println!("opcode        count      %");
+
+    let mut histogram: Vec<(usize, u64)> = statistics.histogram.iter()
+        .copied()
+        .enumerate()
+        .filter(|(_, count)| *count != 0)
+        .collect();
+
+    histogram.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+
+    for (opcode, count) in histogram {
+        let share = count as f64 * 100.0 / statistics.instructions as f64;
+
+        println!("    {:02x} {:12} {:6.2}", opcode, count, share);
+    }
+
+    if speed < TARGET_SPEED {
+        return Err(format!("ran at {:.1}x real-time, below the {}x target",
+                           speed, TARGET_SPEED).into());
+    }
+    Ok(())
+}
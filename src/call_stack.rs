@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::symbols::Symbols;
+
+const MAX_ANOMALIES: usize = 64;
+const MAX_DEPTH:     usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind:           CallKind,
+    pub from:           u16,
+    pub target:         u16,
+    pub return_address: u16,
+    pub return_sp:      u16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackAnomaly {
+    UnknownReturn          { to: u16 },
+    ReturnAddressChanged   { expected: u16, actual: u16 },
+    SkippedFrames          { count: usize },
+    PoppedReturnAddress    { return_address: u16 },
+    ExchangedReturnAddress { old: u16, new: u16 },
+    OverwroteReturnAddress { return_address: u16 },
+    StackPointerReloaded   { dropped: usize },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Anomaly {
+    pub pc:   u16,
+    pub kind: StackAnomaly,
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}: ", self.pc)?;
+
+        match self.kind {
+            StackAnomaly::UnknownReturn { to } =>
+                write!(f, "RET to {:04x}, which was never pushed", to),
+            StackAnomaly::ReturnAddressChanged { expected, actual } =>
+                write!(f, "RET to {:04x}, expected {:04x}", actual, expected),
+            StackAnomaly::SkippedFrames { count } =>
+                write!(f, "RET skipped {} frames", count),
+            StackAnomaly::PoppedReturnAddress { return_address } =>
+                write!(f, "POP discarded return address {:04x}", return_address),
+            StackAnomaly::ExchangedReturnAddress { old, new } =>
+                write!(f, "XTHL replaced return address {:04x} with {:04x}", old, new),
+            StackAnomaly::OverwroteReturnAddress { return_address } =>
+                write!(f, "PUSH overwrote return address {:04x}", return_address),
+            StackAnomaly::StackPointerReloaded { dropped } =>
+                write!(f, "SP reloaded, dropping {} frames", dropped),
+        }
+    }
+}
+
+// Mirrors the return addresses the program pushes so a backtrace can be
+// produced at any point. Anything that touches a return address other than
+// a matching RET is recorded as an anomaly and the shadow stack is brought
+// back in line with SP.
+#[derive(Clone)]
+pub struct CallStack {
+    frames:    Vec<CallFrame>,
+    anomalies: VecDeque<Anomaly>,
+    total:     u64,
+}
+
+impl CallStack {
+    pub fn new() -> CallStack {
+        CallStack {
+            frames:    Vec::new(),
+            anomalies: VecDeque::new(),
+            total:     0,
+        }
+    }
+
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    // The most recent anomalies, oldest first.
+    pub fn anomalies(&self) -> impl Iterator<Item = &Anomaly> {
+        self.anomalies.iter()
+    }
+
+    pub fn anomaly_count(&self) -> u64 {
+        self.total
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    // The return address goes into return_sp, so frames at or below that
+    // slot were left behind by code that moved SP without returning. The
+    // depth is capped too, keeping the innermost frames.
+    pub fn call(&mut self, frame: CallFrame) {
+        while self.frames.last().is_some_and(|last| last.return_sp <= frame.return_sp) {
+            self.frames.pop();
+        }
+        if self.frames.len() == MAX_DEPTH {
+            self.frames.remove(0);
+        }
+        self.frames.push(frame);
+    }
+
+    // return_sp is where the popped return address was stored.
+    pub fn ret(&mut self, pc: u16, return_sp: u16, to: u16) {
+        match self.frames.iter().rposition(|frame| frame.return_sp == return_sp) {
+            Some(index) => {
+                let skipped  = self.frames.len() - index - 1;
+                let expected = self.frames[index].return_address;
+
+                if skipped > 0 {
+                    self.flag(pc, StackAnomaly::SkippedFrames { count: skipped });
+                }
+                if expected != to {
+                    self.flag(pc, StackAnomaly::ReturnAddressChanged { expected, actual: to });
+                }
+                self.frames.truncate(index);
+            }
+            None => {
+                self.flag(pc, StackAnomaly::UnknownReturn { to });
+                self.drop_above(return_sp.wrapping_add(2));
+            }
+        }
+    }
+
+    pub fn pop(&mut self, pc: u16, sp: u16) {
+        if let Some(frame) = self.frames.last().copied() {
+            if frame.return_sp < sp {
+                self.flag(pc, StackAnomaly::PoppedReturnAddress {
+                    return_address: frame.return_address,
+                });
+                self.drop_above(sp);
+            }
+        }
+    }
+
+    pub fn push(&mut self, pc: u16, sp: u16) {
+        let overwritten = self.frames.iter()
+            .find(|frame| frame.return_sp.wrapping_sub(sp).wrapping_add(1) <= 2)
+            .copied();
+
+        if let Some(frame) = overwritten {
+            self.flag(pc, StackAnomaly::OverwroteReturnAddress {
+                return_address: frame.return_address,
+            });
+        }
+    }
+
+    pub fn exchange(&mut self, pc: u16, sp: u16, new: u16) {
+        if let Some(frame) = self.frames.last_mut() {
+            if frame.return_sp == sp && frame.return_address != new {
+                let old = frame.return_address;
+
+                frame.return_address = new;
+                self.flag(pc, StackAnomaly::ExchangedReturnAddress { old, new });
+            }
+        }
+    }
+
+    pub fn load_sp(&mut self, pc: u16, sp: u16) {
+        let dropped = self.drop_above(sp);
+
+        if dropped > 0 {
+            self.flag(pc, StackAnomaly::StackPointerReloaded { dropped });
+        }
+    }
+
+    fn drop_above(&mut self, sp: u16) -> usize {
+        let before = self.frames.len();
+
+        while self.frames.last().is_some_and(|frame| frame.return_sp < sp) {
+            self.frames.pop();
+        }
+        before - self.frames.len()
+    }
+
+    fn flag(&mut self, pc: u16, kind: StackAnomaly) {
+        if self.anomalies.len() == MAX_ANOMALIES {
+            self.anomalies.pop_front();
+        }
+        self.anomalies.push_back(Anomaly { pc, kind });
+        self.total += 1;
+    }
+
+    // Innermost frame first, starting at the instruction about to run.
+    pub fn backtrace(&self, pc: u16, symbols: &Symbols) -> String {
+        let location = |address: u16| match symbols.label(address) {
+            Some(label) => format!("{:04x}  {}", address, label),
+            None        => format!("{:04x}", address),
+        };
+        let mut lines = vec![format!("#0  {}", location(pc))];
+
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            let kind = match frame.kind {
+                CallKind::Call      => "CALL",
+                CallKind::Rst       => "RST",
+                CallKind::Interrupt => "interrupt to",
+            };
+
+            lines.push(format!("#{:<2} {}  ({} {})", depth + 1, location(frame.from),
+                               kind, symbols.name(frame.target)));
+        }
+
+        if self.total > 0 {
+            lines.push(format!("{} stack anomalies, most recent last:", self.total));
+            for anomaly in self.anomalies.iter().rev().take(8).rev() {
+                lines.push(format!("    {}", anomaly));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,365 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::memory::Memory;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Condition {
+    pub address: u16,
+    pub equal:   bool,
+    pub value:   u8,
+}
+
+impl Condition {
+    fn holds(&self, memory: &Memory) -> bool {
+        (memory.read8(self.address) == self.value) == self.equal
+    }
+}
+
+// The text forms are
+//   2000=05               freeze 0x2000 at 0x05
+//   2000=05 if 2001==01   only while 0x2001 holds 0x01 (or != for the opposite)
+//   0a1b:3d:00            patch ROM at 0x0a1b from 0x3d to 0x00
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Code {
+    Freeze      { address: u16, value: u8 },
+    Conditional { address: u16, value: u8, condition: Condition },
+    Patch       { address: u16, old: u8, new: u8 },
+}
+
+fn hex16(text: &str) -> Result<u16, String> {
+    u16::from_str_radix(text, 16).map_err(|_| format!("bad address {}", text))
+}
+
+fn hex8(text: &str) -> Result<u8, String> {
+    u8::from_str_radix(text, 16).map_err(|_| format!("bad value {}", text))
+}
+
+fn assignment(text: &str) -> Result<(u16, u8), String> {
+    let (address, value) = text.split_once('=').ok_or(format!("bad code {}", text))?;
+
+    Ok((hex16(address)?, hex8(value)?))
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Code, String> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+
+        match words.as_slice() {
+            [patch] if patch.contains(':') => {
+                let fields: Vec<&str> = patch.split(':').collect();
+                let [address, old, new] = fields.as_slice() else {
+                    return Err(format!("bad patch {}", patch));
+                };
+
+                Ok(Code::Patch { address: hex16(address)?, old: hex8(old)?, new: hex8(new)? })
+            }
+            [write] => {
+                let (address, value) = assignment(write)?;
+
+                Ok(Code::Freeze { address, value })
+            }
+            [write, "if", condition] => {
+                let (address, value) = assignment(write)?;
+                let (equal, (left, right)) = match condition.split_once("!=") {
+                    Some(sides) => (false, sides),
+                    None        => (true, condition.split_once("==")
+                        .ok_or(format!("bad condition {}", condition))?),
+                };
+                let condition = Condition { address: hex16(left)?, equal, value: hex8(right)? };
+
+                Ok(Code::Conditional { address, value, condition })
+            }
+            _ => Err(format!("bad code {}", text)),
+        }
+    }
+}
+
+impl Code {
+    // Codes are parsed without knowing the machine, so every address they
+    // touch is checked against the memory before they are used.
+    fn check(&self, memory: &Memory) -> Result<(), Box<dyn Error>> {
+        let inside = |address: u16| usize::from(address) < memory.len();
+        let fits   = match *self {
+            Code::Freeze { address, .. } | Code::Patch { address, .. } => inside(address),
+            Code::Conditional { address, condition, .. } => {
+                inside(address) && inside(condition.address)
+            }
+        };
+
+        if !fits {
+            return Err(format!("cheat {} is outside the {:04x} bytes of memory",
+                               self, memory.len()).into());
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Code::Freeze { address, value } => write!(f, "{:04x}={:02x}", address, value),
+            Code::Conditional { address, value, condition } => {
+                write!(f, "{:04x}={:02x} if {:04x}{}{:02x}", address, value, condition.address,
+                       if condition.equal { "==" } else { "!=" }, condition.value)
+            }
+            Code::Patch { address, old, new } => write!(f, "{:04x}:{:02x}:{:02x}", address, old, new),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cheat {
+    pub code:        Code,
+    pub description: String,
+    pub enabled:     bool,
+    applied:         bool,
+}
+
+impl Cheat {
+    fn new(code: Code, description: &str, enabled: bool) -> Cheat {
+        Cheat {
+            code,
+            description: description.to_string(),
+            enabled,
+            applied:     false,
+        }
+    }
+
+    fn patch(&mut self, apply: bool, memory: &mut Memory) -> Result<(), Box<dyn Error>> {
+        let Code::Patch { address, old, new } = self.code else {
+            return Ok(());
+        };
+        if apply == self.applied {
+            return Ok(());
+        }
+        let current = memory.read8(address);
+
+        match (apply, self.applied) {
+            (true, false) if current == old => memory.write8(address, new),
+            (true, false) => {
+                return Err(format!("patch {} expects {:02x} at {:04x} but found {:02x}",
+                                   self.code, old, address, current).into());
+            }
+            (false, true) if current == new => memory.write8(address, old),
+            _ => {}
+        }
+        self.applied = apply;
+        Ok(())
+    }
+}
+
+// Freezes and conditional writes are applied once per frame. A patch is
+// applied when it is enabled, only if the byte still holds the expected
+// original value, and restored when it is disabled.
+#[derive(Clone)]
+pub struct Cheats {
+    cheats: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn new() -> Cheats {
+        Cheats {
+            cheats: Vec::new(),
+        }
+    }
+
+    // One code per line followed by an optional description. A leading `-`
+    // loads the cheat disabled and `;` starts a comment.
+    pub fn parse(text: &str) -> Result<Cheats, Box<dyn Error>> {
+        let mut cheats = Cheats::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line.trim_start()),
+                None       => (true, line),
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let length = if words.get(1) == Some(&"if") { 3 } else { 1 };
+            let code = words[..length.min(words.len())].join(" ").parse::<Code>()
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+
+            cheats.cheats.push(Cheat::new(code, &words[length.min(words.len())..].join(" "), enabled));
+        }
+        Ok(cheats)
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats, Box<dyn Error>> {
+        Cheats::parse(&std::fs::read_to_string(path)?)
+            .map_err(|error| format!("{}: {}", path.display(), error).into())
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn add(&mut self, code: Code, description: &str) -> usize {
+        self.cheats.push(Cheat::new(code, description, false));
+        self.cheats.len() - 1
+    }
+
+    pub fn set_enabled(&mut self, index: usize, enabled: bool, memory: &mut Memory)
+            -> Result<(), Box<dyn Error>> {
+        let cheat = self.cheats.get_mut(index).ok_or(format!("no cheat {}", index + 1))?;
+
+        if enabled {
+            cheat.code.check(memory)?;
+        }
+        cheat.patch(enabled, memory)?;
+        cheat.enabled = enabled;
+        Ok(())
+    }
+
+    // Applies the patches of cheats that were loaded enabled.
+    pub fn install(&mut self, memory: &mut Memory) -> Result<(), Box<dyn Error>> {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            cheat.code.check(memory)?;
+        }
+        for cheat in self.cheats.iter_mut().filter(|cheat| cheat.enabled) {
+            cheat.patch(true, memory)?;
+        }
+        Ok(())
+    }
+
+    // Puts back the original bytes of every applied patch.
+    pub fn uninstall(&mut self, memory: &mut Memory) -> Result<(), Box<dyn Error>> {
+        for cheat in &mut self.cheats {
+            cheat.patch(false, memory)?;
+        }
+        Ok(())
+    }
+
+    pub fn toggle(&mut self, index: usize, memory: &mut Memory) -> Result<bool, Box<dyn Error>> {
+        let enabled = !self.cheats.get(index).ok_or(format!("no cheat {}", index + 1))?.enabled;
+
+        self.set_enabled(index, enabled, memory)?;
+        Ok(enabled)
+    }
+
+    // Codes that do not fit the memory are skipped, in case they were
+    // enabled without being installed.
+    pub fn apply(&self, memory: &mut Memory) {
+        for cheat in self.cheats.iter().filter(|cheat| cheat.enabled) {
+            if cheat.code.check(memory).is_err() {
+                continue;
+            }
+            match cheat.code {
+                Code::Freeze { address, value } => memory.write8(address, value),
+                Code::Conditional { address, value, condition } if condition.holds(memory) => {
+                    memory.write8(address, value);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // The enabled cheats in the file format, for storing alongside a
+    // recording of a session.
+    pub fn active(&self) -> String {
+        self.cheats.iter()
+            .filter(|cheat| cheat.enabled)
+            .map(|cheat| format!("{} {}", cheat.code, cheat.description).trim_end().to_string() + "\n")
+            .collect()
+    }
+}
+
+impl Default for Cheats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+; lives and a patch
+21ff=03 Infinite lives
+-2010=00 if 2011!=01
+0002:00:ff Patched byte
+";
+
+    #[test]
+    fn parses_and_prints_codes() {
+        let cheats = Cheats::parse(FILE).unwrap();
+        let codes: Vec<String> = cheats.cheats().iter().map(|cheat| cheat.code.to_string()).collect();
+
+        assert_eq!(codes, ["21ff=03", "2010=00 if 2011!=01", "0002:00:ff"]);
+        assert_eq!(cheats.cheats()[0].description, "Infinite lives");
+        assert!(!cheats.cheats()[1].enabled);
+        assert_eq!(cheats.active(), "21ff=03 Infinite lives\n0002:00:ff Patched byte\n");
+        assert!(Cheats::parse("2000=xyz\n").is_err());
+        assert!(Cheats::parse("2000:01\n").is_err());
+    }
+
+    #[test]
+    fn applies_freezes_conditions_and_verified_patches() {
+        let mut memory = Memory::new();
+        let mut cheats = Cheats::new();
+        let freeze     = cheats.add("2000=07".parse().unwrap(), "");
+        let when       = cheats.add("2001=09 if 2002==01".parse().unwrap(), "");
+        let patch      = cheats.add("0005:00:3c".parse().unwrap(), "");
+
+        cheats.set_enabled(freeze, true, &mut memory).unwrap();
+        cheats.set_enabled(when, true, &mut memory).unwrap();
+        cheats.apply(&mut memory);
+        assert_eq!((memory.read8(0x2000), memory.read8(0x2001)), (0x07, 0x00));
+
+        memory.write8(0x2002, 0x01);
+        cheats.apply(&mut memory);
+        assert_eq!(memory.read8(0x2001), 0x09);
+
+        assert!(cheats.toggle(patch, &mut memory).unwrap());
+        assert_eq!(memory.read8(0x0005), 0x3c);
+        assert!(!cheats.toggle(patch, &mut memory).unwrap());
+        assert_eq!(memory.read8(0x0005), 0x00);
+
+        memory.write8(0x0005, 0x11);
+        assert!(cheats.toggle(patch, &mut memory).is_err());
+        assert!(!cheats.cheats()[patch].enabled);
+    }
+
+    #[test]
+    fn codes_outside_memory_are_rejected() {
+        let mut memory = Memory::new();
+        let mut cheats = Cheats::new();
+        let freeze     = cheats.add("6000=01".parse().unwrap(), "");
+        let when       = cheats.add("2000=01 if 5000==00".parse().unwrap(), "");
+        let patch      = cheats.add("ffff:00:01".parse().unwrap(), "");
+
+        for index in [freeze, when, patch] {
+            assert!(cheats.set_enabled(index, true, &mut memory).is_err());
+            assert!(!cheats.cheats()[index].enabled);
+        }
+
+        let mut loaded = Cheats::parse("6000=01\n").unwrap();
+
+        assert!(loaded.install(&mut memory).is_err());
+        loaded.apply(&mut memory);
+        loaded.uninstall(&mut memory).unwrap();
+        assert!(Cheats::parse("-ffff:00:01\n").unwrap().install(&mut memory).is_ok());
+    }
+
+    #[test]
+    fn uninstall_restores_patched_bytes() {
+        let mut memory = Memory::new();
+        let mut cheats = Cheats::parse(FILE).unwrap();
+
+        cheats.install(&mut memory).unwrap();
+        assert_eq!(memory.read8(0x0002), 0xff);
+
+        cheats.uninstall(&mut memory).unwrap();
+        assert_eq!(memory.read8(0x0002), 0x00);
+    }
+}
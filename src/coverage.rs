@@ -0,0 +1,196 @@
+use std::error::Error;
+use std::fmt::Write;
+
+use crate::cpu::Cpu;
+use crate::disassembler;
+use crate::memory::Memory;
+use crate::png;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Opcode,
+    Operand,
+    Read,
+    Write,
+}
+
+pub const ACCESSES: [Access; 4] = [Access::Opcode, Access::Operand, Access::Read, Access::Write];
+
+impl Access {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::Opcode  => "opcode",
+            Access::Operand => "operand",
+            Access::Read    => "read",
+            Access::Write   => "write",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Coverage {
+    map: Vec<u8>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            map: vec![0; 0x10000],
+        }
+    }
+
+    pub fn record(&mut self, address: u16, access: Access) {
+        self.map[usize::from(address)] |= access.bit();
+    }
+
+    pub fn is(&self, address: u16, access: Access) -> bool {
+        self.map[usize::from(address)] & access.bit() != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.map.fill(0);
+    }
+
+    // Inclusive address ranges that saw the given kind of access.
+    pub fn ranges(&self, access: Access) -> Vec<(u16, u16)> {
+        let mut ranges: Vec<(u16, u16)> = Vec::new();
+
+        for address in 0..=0xffffu16 {
+            if !self.is(address, access) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == address => *end = address,
+                _ => ranges.push((address, address)),
+            }
+        }
+        ranges
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+
+        for (index, access) in ACCESSES.iter().enumerate() {
+            let ranges: Vec<String> = self.ranges(*access).iter()
+                .map(|(start, end)| format!("[{}, {}]", start, end))
+                .collect();
+            let separator = if index + 1 < ACCESSES.len() { "," } else { "" };
+
+            let _ = writeln!(json, "  \"{}\": [{}]{}", access.name(), ranges.join(", "), separator);
+        }
+        json.push_str("}\n");
+        json
+    }
+
+    // One pixel per address, 256 addresses to a row. Green marks code
+    // (bright for opcodes, dark for operands), blue data reads and red
+    // writes, so RAM that is both read and written shows up magenta.
+    pub fn heatmap(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.map.len() * 3);
+
+        for address in 0..=0xffffu16 {
+            let green = if self.is(address, Access::Opcode) {
+                0xff
+            } else if self.is(address, Access::Operand) {
+                0x80
+            } else {
+                0x00
+            };
+
+            rgb.push(if self.is(address, Access::Write) { 0xff } else { 0x00 });
+            rgb.push(green);
+            rgb.push(if self.is(address, Access::Read) { 0xff } else { 0x00 });
+        }
+        png::encode(256, 256, &rgb)
+    }
+
+    // A linear sweep disassembly of memory[start..end]. Bytes that were only
+    // ever read as data are listed as DB, and the sweep never decodes across
+    // an executed opcode so it stays in step with the code that actually ran.
+    pub fn listing(&self, cpu: Cpu, memory: &Memory, start: u16, end: u16)
+            -> Result<String, Box<dyn Error>> {
+        let mut listing = String::new();
+        let mut address = start;
+
+        while address < end {
+            let mut bytes = [0u8; 3];
+
+            memory.read(address.into(), &mut bytes);
+
+            let (instruction, text) = disassembler::line(cpu, &bytes)?;
+            let length              = u16::from(instruction.len());
+            let overlaps            = (1..length)
+                .any(|offset| self.is(address.wrapping_add(offset), Access::Opcode));
+            let executed            = self.is(address, Access::Opcode);
+
+            if !executed && (overlaps || self.is(address, Access::Read)) {
+                let _ = writeln!(listing, "{:04x}  {:02x}        DB   {:#x}  ; data",
+                                 address, bytes[0], bytes[0]);
+                address += 1;
+                continue;
+            }
+
+            let _ = write!(listing, "{:04x}  {}", address, text);
+            if !executed {
+                listing.push_str("  ; not reached");
+            }
+            listing.push('\n');
+
+            address = address.saturating_add(length);
+        }
+        Ok(listing)
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_merge_adjacent_addresses() {
+        let mut coverage = Coverage::new();
+
+        for address in [0, 1, 2, 5, 0xffff] {
+            coverage.record(address, Access::Opcode);
+        }
+        coverage.record(3, Access::Read);
+
+        assert_eq!(coverage.ranges(Access::Opcode), vec![(0, 2), (5, 5), (0xffff, 0xffff)]);
+        assert_eq!(coverage.ranges(Access::Read), vec![(3, 3)]);
+        assert!(coverage.to_json().contains("\"opcode\": [[0, 2], [5, 5], [65535, 65535]],"));
+    }
+
+    #[test]
+    fn listing_marks_unreached_code_and_data() {
+        let mut memory   = Memory::with_size(0x10);
+        let mut coverage = Coverage::new();
+
+        // JMP 0x0004, one data byte, then NOP at 0x0004 and a dead NOP.
+        memory.write(0, &[0xc3, 0x04, 0x00, 0x42, 0x00, 0x00]);
+        coverage.record(0, Access::Opcode);
+        coverage.record(1, Access::Operand);
+        coverage.record(2, Access::Operand);
+        coverage.record(3, Access::Read);
+        coverage.record(4, Access::Opcode);
+
+        let listing = coverage.listing(Cpu::I8080, &memory, 0, 6).unwrap();
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("0000  c3 04 00  JMP"));
+        assert!(!lines[0].contains("not reached"));
+        assert!(lines[1].starts_with("0003") && lines[1].ends_with("; data"));
+        assert!(lines[2].starts_with("0004") && !lines[2].contains("not reached"));
+        assert!(lines[3].starts_with("0005") && lines[3].ends_with("; not reached"));
+    }
+}
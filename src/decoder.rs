@@ -1,42 +1,82 @@
 use std::error::Error;
 
-use crate::instruction::Instruction;
+use crate::cpu::Cpu;
+use crate::instruction::{Instruction, Register, RegisterPair};
 
-pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
+pub fn is_undocumented(cpu: Cpu, opcode: u8) -> bool {
+    match cpu {
+        Cpu::I8080 => matches!(opcode, 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38
+            | 0xcb | 0xd9 | 0xdd | 0xed | 0xfd),
+        Cpu::I8085 => matches!(opcode, 0x08 | 0x10 | 0x18 | 0x28 | 0x38
+            | 0xcb | 0xd9 | 0xdd | 0xed | 0xfd),
+    }
+}
+
+pub fn decode(cpu: Cpu, instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
+    if cpu == Cpu::I8085 {
+        if let Some(instruction) = decode_8085(instruction)? {
+            return Ok(instruction);
+        }
+    }
+    decode_8080(instruction)
+}
+
+fn decode_8085(instruction: &[u8]) -> Result<Option<Instruction>, Box<dyn Error>> {
+    let opcode = instruction[0];
+
+    let instruction = match opcode {
+        0x08 => Instruction::Dsub,
+        0x10 => Instruction::Arhl,
+        0x18 => Instruction::Rdel,
+        0x20 => Instruction::Rim,
+        0x28 => Instruction::Ldhi { data: instruction[1] },
+        0x30 => Instruction::Sim,
+        0x38 => Instruction::Ldsi { data: instruction[1] },
+        0xcb => Instruction::Rstv,
+        0xd9 => Instruction::Shlx,
+        0xdd => Instruction::Jnk { exp: u16::from_le_bytes(instruction[1..3].try_into()?) },
+        0xed => Instruction::Lhlx,
+        0xfd => Instruction::Jk  { exp: u16::from_le_bytes(instruction[1..3].try_into()?) },
+        _    => return Ok(None),
+    };
+    Ok(Some(instruction))
+}
+
+fn decode_8080(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
     let opcode = instruction[0];
 
     let instruction = match opcode {
-        0x00 => {
+        0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 => {
             Instruction::Nop
         }
         0x01 | 0x11 | 0x21 | 0x31 => {
             let rp   = (opcode >> 4) & 0b11;
             let data = u16::from_le_bytes(instruction[1..3].try_into()?);
 
-            Instruction::Lxi { rp, data }
+            Instruction::Lxi { rp: RegisterPair::from(rp), data }
         }
         0x02 | 0x12 => {
             let rp = (opcode >> 4) & 0b1;
 
-            Instruction::Stax { rp }
+            Instruction::Stax { rp: RegisterPair::from(rp) }
         }
         0x03 | 0x13 | 0x23 | 0x33 => {
             let rp = (opcode >> 4) & 0b11;
 
-            Instruction::Inx { rp }
+            Instruction::Inx { rp: RegisterPair::from(rp) }
         }
         0x04 | 0x0c | 0x14 | 0x1c | 0x24 | 0x2c | 0x34 | 0x3c => {
-            let reg = (opcode >> 3) & 0b111;
+            let reg = Register::from(opcode >> 3);
 
             Instruction::Inr { reg }
         }
         0x05 | 0x0d | 0x15 | 0x1d | 0x25 | 0x2d | 0x35 | 0x3d => {
-            let reg = (opcode >> 3) & 0b111;
+            let reg = Register::from(opcode >> 3);
 
             Instruction::Dcr { reg }
         }
         0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x36 | 0x3e => {
-            let reg  = (opcode >> 3) & 0b111;
+            let reg  = Register::from(opcode >> 3);
             let data = instruction[1];
 
             Instruction::Mvi { reg, data }
@@ -55,17 +95,17 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
         0x09 | 0x19 | 0x29 | 0x39 => {
             let rp = (opcode >> 4) & 0b11;
 
-            Instruction::Dad { rp }
+            Instruction::Dad { rp: RegisterPair::from(rp) }
         }
         0x0a | 0x1a => {
             let rp = (opcode >> 4) & 0b1;
 
-            Instruction::Ldax { rp }
+            Instruction::Ldax { rp: RegisterPair::from(rp) }
         }
         0x0b | 0x1b | 0x2b | 0x3b => {
             let rp = (opcode >> 4) & 0b11;
 
-            Instruction::Dcx { rp }
+            Instruction::Dcx { rp: RegisterPair::from(rp) }
         }
         0x22 | 0x2a | 0x32 | 0x3a => {
             let op  = (opcode >> 3) & 0b11;
@@ -95,8 +135,8 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
             }
         }
         0x40..=0x75 | 0x77..=0x7f => {
-            let dst = (opcode >> 3) & 0b111;
-            let src =  opcode       & 0b111;
+            let dst = Register::from(opcode >> 3);
+            let src = Register::from(opcode);
 
             Instruction::Mov { dst, src }
         }
@@ -105,7 +145,7 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
         }
         0x80..=0xbf => {
             let op  = (opcode >> 3) & 0b111;
-            let reg =  opcode       & 0b111;
+            let reg = Register::from(opcode);
 
             match op {
                 0b000 => Instruction::Add { reg },
@@ -145,7 +185,7 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
 
             if rp == 0b11 { rp += 1; }
 
-            Instruction::Pop { rp }
+            Instruction::Pop { rp: RegisterPair::from(rp) }
         }
         0xc2 | 0xc3 | 0xca | 0xd2 | 0xda | 0xe2 | 0xea | 0xf2 | 0xfa => {
             let op  = (opcode >> 3) & 0b111;
@@ -196,7 +236,7 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
 
             if rp == 0b11 { rp += 1; }
 
-            Instruction::Push { rp }
+            Instruction::Push { rp: RegisterPair::from(rp) }
         }
         0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe => {
             let op   = (opcode >> 3) & 0b111;
@@ -250,7 +290,100 @@ pub fn decode(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
         0xf9 => {
             Instruction::Sphl
         }
-        _ => panic!("invalid opcode: {:02x}", opcode),
+        0xcb => {
+            let exp = u16::from_le_bytes(instruction[1..3].try_into()?);
+
+            Instruction::Jmp { exp }
+        }
+        0xd9 => {
+            Instruction::Ret
+        }
+        0xdd | 0xed | 0xfd => {
+            let sub = u16::from_le_bytes(instruction[1..3].try_into()?);
+
+            Instruction::Call { sub }
+        }
     };
     Ok(instruction)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flag::Flag;
+
+    const ALL_FLAGS: u8 = Flag::Sign as u8 | Flag::Zero as u8 | Flag::AuxCarry as u8
+        | Flag::Parity as u8 | Flag::Carry as u8;
+
+    const OVERFLOW_FLAGS: u8 = Flag::Overflow as u8 | Flag::Underflow as u8;
+
+    #[test]
+    fn register_fields_are_decoded() {
+        let decode = |bytes: &[u8]| decode(Cpu::I8080, bytes).unwrap();
+
+        assert_eq!(decode(&[0x78]), Instruction::Mov { dst: Register::A, src: Register::B });
+        assert_eq!(decode(&[0x77]), Instruction::Mov { dst: Register::M, src: Register::A });
+        assert_eq!(decode(&[0x34]), Instruction::Inr { reg: Register::M });
+        assert_eq!(decode(&[0x0e, 0x42]), Instruction::Mvi { reg: Register::C, data: 0x42 });
+        assert_eq!(decode(&[0x96]), Instruction::Sub { reg: Register::M });
+        assert_eq!(decode(&[0xbf]), Instruction::Cmp { reg: Register::A });
+        assert_eq!(decode(&[0x1a]), Instruction::Ldax { rp: RegisterPair::D });
+        assert_eq!(decode(&[0x31, 0x00, 0x24]), Instruction::Lxi { rp: RegisterPair::Sp, data: 0x2400 });
+        assert_eq!(decode(&[0xc5]), Instruction::Push { rp: RegisterPair::B });
+        assert_eq!(decode(&[0xf1]), Instruction::Pop { rp: RegisterPair::Psw });
+        assert_eq!(decode(&[0x29]), Instruction::Dad { rp: RegisterPair::H });
+    }
+
+    #[test]
+    fn undocumented_opcodes_depend_on_the_cpu() {
+        assert_eq!(decode(Cpu::I8080, &[0x08]).unwrap(), Instruction::Nop);
+        assert_eq!(decode(Cpu::I8085, &[0x08]).unwrap(), Instruction::Dsub);
+        assert_eq!(decode(Cpu::I8085, &[0xdd, 0x34, 0x12]).unwrap(), Instruction::Jnk { exp: 0x1234 });
+        assert!(is_undocumented(Cpu::I8080, 0x20));
+        assert!(!is_undocumented(Cpu::I8085, 0x20));
+    }
+
+    #[test]
+    fn memory_accesses_follow_the_operands() {
+        let decode = |bytes: &[u8]| decode(Cpu::I8080, bytes).unwrap();
+
+        assert!( decode(&[0x7e]).reads_memory());
+        assert!(!decode(&[0x7e]).writes_memory());
+        assert!(!decode(&[0x77]).reads_memory());
+        assert!( decode(&[0x77]).writes_memory());
+        assert!( decode(&[0x34]).reads_memory() && decode(&[0x34]).writes_memory());
+        assert!(!decode(&[0x3c]).reads_memory() && !decode(&[0x3c]).writes_memory());
+        assert!( decode(&[0xcd, 0x00, 0x10]).writes_memory());
+        assert!( decode(&[0xc9]).reads_memory());
+    }
+
+    #[test]
+    fn flags_written_on_the_8080() {
+        let flags = |bytes: &[u8]| decode(Cpu::I8080, bytes).unwrap().flags_written(Cpu::I8080);
+
+        assert_eq!(flags(&[0x37]), Flag::Carry as u8);
+        assert_eq!(flags(&[0x3c]), ALL_FLAGS & !(Flag::Carry as u8));
+        assert_eq!(flags(&[0x80]), ALL_FLAGS);
+        assert_eq!(flags(&[0xfe, 0x00]), ALL_FLAGS);
+        assert_eq!(flags(&[0xf1]), ALL_FLAGS);
+        assert_eq!(flags(&[0x03]), 0);
+        assert_eq!(flags(&[0xc1]), 0);
+        assert_eq!(flags(&[0x78]), 0);
+    }
+
+    #[test]
+    fn flags_written_on_the_8085() {
+        let flags = |bytes: &[u8]| decode(Cpu::I8085, bytes).unwrap().flags_written(Cpu::I8085);
+
+        assert_eq!(flags(&[0x37]), Flag::Carry as u8);
+        assert_eq!(flags(&[0x3c]), ALL_FLAGS & !(Flag::Carry as u8));
+        assert_eq!(flags(&[0x80]), ALL_FLAGS | OVERFLOW_FLAGS);
+        assert_eq!(flags(&[0xfe, 0x00]), ALL_FLAGS | OVERFLOW_FLAGS);
+        assert_eq!(flags(&[0xf1]), ALL_FLAGS | OVERFLOW_FLAGS);
+        assert_eq!(flags(&[0x03]), Flag::Underflow as u8);
+        assert_eq!(flags(&[0x0b]), Flag::Underflow as u8);
+        assert_eq!(flags(&[0x08]), ALL_FLAGS | OVERFLOW_FLAGS);
+        assert_eq!(flags(&[0x18]), Flag::Carry as u8 | Flag::Overflow as u8);
+        assert_eq!(flags(&[0xc1]), 0);
+    }
+}
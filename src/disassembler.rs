@@ -1,360 +1,327 @@
 use std::fmt::Write;
 use std::error::Error;
 
+use crate::cpu::Cpu;
 use crate::decoder;
-use crate::instruction::Instruction;
+use crate::instruction::{Instruction, Register, RegisterPair};
 
-fn print_opcodes(instruction: &[u8], instruction_length: usize) {
+fn opcodes(instruction: &[u8], instruction_length: usize) -> String {
     let mut opcodes = String::new();
 
     for byte in instruction.iter().take(instruction_length) {
         let _ = write!(opcodes, "{:02x} ", byte);
     }
 
-    print!("{: <10}", opcodes)
+    format!("{: <10}", opcodes)
 }
 
-fn register(reg: u8) -> &'static str {
+fn register(reg: Register) -> &'static str {
     match reg {
-        0b000 => "B",
-        0b001 => "C",
-        0b010 => "D",
-        0b011 => "E",
-        0b100 => "H",
-        0b101 => "L",
-        0b110 => "M",
-        0b111 => "A",
-        _     => unreachable!(),
+        Register::B => "B",
+        Register::C => "C",
+        Register::D => "D",
+        Register::E => "E",
+        Register::H => "H",
+        Register::L => "L",
+        Register::M => "M",
+        Register::A => "A",
     }
 }
 
-fn register_pair(rp: u8) -> &'static str {
+fn register_pair(rp: RegisterPair) -> &'static str {
     match rp {
-        0b000 => "B",
-        0b001 => "D",
-        0b010 => "H",
-        0b011 => "SP",
-        0b100 => "PSW",
-        _     => unreachable!(),
+        RegisterPair::B   => "B",
+        RegisterPair::D   => "D",
+        RegisterPair::H   => "H",
+        RegisterPair::Sp  => "SP",
+        RegisterPair::Psw => "PSW",
     }
 }
 
-pub fn disassemble(instruction: &[u8]) -> Result<Instruction, Box<dyn Error>> {
-    let decoded_instruction = decoder::decode(instruction)?;
-
-    match decoded_instruction {
+fn mnemonic(instruction: Instruction) -> String {
+    match instruction {
         Instruction::Stc => {
-            print_opcodes(instruction, 1);
-            println!("STC");
+            "STC".to_string()
         }
         Instruction::Cmc => {
-            print_opcodes(instruction, 1);
-            println!("CMC");
+            "CMC".to_string()
         }
         Instruction::Inr { reg } => {
-            print_opcodes(instruction, 1);
-            println!("INR  {}", register(reg));
+            format!("INR  {}", register(reg))
         }
         Instruction::Dcr { reg } => {
-            print_opcodes(instruction, 1);
-            println!("DCR  {}", register(reg));
+            format!("DCR  {}", register(reg))
         }
         Instruction::Cma => {
-            print_opcodes(instruction, 1);
-            println!("CMA");
+            "CMA".to_string()
         }
         Instruction::Daa => {
-            print_opcodes(instruction, 1);
-            println!("DAA");
+            "DAA".to_string()
         }
         Instruction::Nop => {
-            print_opcodes(instruction, 1);
-            println!("NOP");
+            "NOP".to_string()
         }
         Instruction::Mov { dst, src } => {
-            print_opcodes(instruction, 1);
-            println!("MOV  {}, {}", register(dst), register(src));
+            format!("MOV  {}, {}", register(dst), register(src))
         }
         Instruction::Stax { rp } => {
-            print_opcodes(instruction, 1);
-            println!("STAX {}", register_pair(rp));
+            format!("STAX {}", register_pair(rp))
         }
         Instruction::Ldax { rp } => {
-            print_opcodes(instruction, 1);
-            println!("LDAX {}", register_pair(rp));
+            format!("LDAX {}", register_pair(rp))
         }
         Instruction::Add { reg } => {
-            print_opcodes(instruction, 1);
-            println!("ADD  {}", register(reg));
+            format!("ADD  {}", register(reg))
         }
         Instruction::Adc { reg } => {
-            print_opcodes(instruction, 1);
-            println!("ADC  {}", register(reg));
+            format!("ADC  {}", register(reg))
         }
         Instruction::Sub { reg } => {
-            print_opcodes(instruction, 1);
-            println!("SUB  {}", register(reg));
+            format!("SUB  {}", register(reg))
         }
         Instruction::Sbb { reg } => {
-            print_opcodes(instruction, 1);
-            println!("SBB  {}", register(reg));
+            format!("SBB  {}", register(reg))
         }
         Instruction::Ana { reg } => {
-            print_opcodes(instruction, 1);
-            println!("ANA  {}", register(reg));
+            format!("ANA  {}", register(reg))
         }
         Instruction::Xra { reg } => {
-            print_opcodes(instruction, 1);
-            println!("XRA  {}", register(reg));
+            format!("XRA  {}", register(reg))
         }
         Instruction::Ora { reg } => {
-            print_opcodes(instruction, 1);
-            println!("ORA  {}", register(reg));
+            format!("ORA  {}", register(reg))
         }
         Instruction::Cmp { reg } => {
-            print_opcodes(instruction, 1);
-            println!("CMP  {}", register(reg));
+            format!("CMP  {}", register(reg))
         }
         Instruction::Rlc => {
-            print_opcodes(instruction, 1);
-            println!("RLC");
+            "RLC".to_string()
         }
         Instruction::Rrc => {
-            print_opcodes(instruction, 1);
-            println!("RRC");
+            "RRC".to_string()
         }
         Instruction::Ral => {
-            print_opcodes(instruction, 1);
-            println!("RAL");
+            "RAL".to_string()
         }
         Instruction::Rar => {
-            print_opcodes(instruction, 1);
-            println!("RAR");
+            "RAR".to_string()
         }
         Instruction::Push { rp } => {
-            print_opcodes(instruction, 1);
-            println!("PUSH {}", register_pair(rp));
+            format!("PUSH {}", register_pair(rp))
         }
         Instruction::Pop { rp } => {
-            print_opcodes(instruction, 1);
-            println!("POP  {}", register_pair(rp));
+            format!("POP  {}", register_pair(rp))
         }
         Instruction::Dad { rp } => {
-            print_opcodes(instruction, 1);
-            println!("DAD  {}", register_pair(rp));
+            format!("DAD  {}", register_pair(rp))
         }
         Instruction::Inx { rp } => {
-            print_opcodes(instruction, 1);
-            println!("INX  {}", register_pair(rp));
+            format!("INX  {}", register_pair(rp))
         }
         Instruction::Dcx { rp } => {
-            print_opcodes(instruction, 1);
-            println!("DCX  {}", register_pair(rp));
+            format!("DCX  {}", register_pair(rp))
         }
         Instruction::Xchg => {
-            print_opcodes(instruction, 1);
-            println!("XCHG");
+            "XCHG".to_string()
         }
         Instruction::Xthl => {
-            print_opcodes(instruction, 1);
-            println!("XTHL");
+            "XTHL".to_string()
         }
         Instruction::Sphl => {
-            print_opcodes(instruction, 1);
-            println!("SPHL");
+            "SPHL".to_string()
         }
         Instruction::Lxi { rp, data } => {
-            print_opcodes(instruction, 3);
-            println!("LXI  {}, {:#x}", register_pair(rp), data);
+            format!("LXI  {}, {:#x}", register_pair(rp), data)
         }
         Instruction::Mvi { reg, data } => {
-            print_opcodes(instruction, 2);
-            println!("MVI  {}, {:#x}", register(reg), data);
+            format!("MVI  {}, {:#x}", register(reg), data)
         }
         Instruction::Adi { data } => {
-            print_opcodes(instruction, 2);
-            println!("ADI  {:#x}", data);
+            format!("ADI  {:#x}", data)
         }
         Instruction::Aci { data } => {
-            print_opcodes(instruction, 2);
-            println!("ACI  {:#x}", data);
+            format!("ACI  {:#x}", data)
         }
         Instruction::Sui { data } => {
-            print_opcodes(instruction, 2);
-            println!("SUI  {:#x}", data);
+            format!("SUI  {:#x}", data)
         }
         Instruction::Sbi { data } => {
-            print_opcodes(instruction, 2);
-            println!("SBI  {:#x}", data);
+            format!("SBI  {:#x}", data)
         }
         Instruction::Ani { data } => {
-            print_opcodes(instruction, 2);
-            println!("ANI  {:#x}", data);
+            format!("ANI  {:#x}", data)
         }
         Instruction::Xri { data } => {
-            print_opcodes(instruction, 2);
-            println!("XRI  {:#x}", data);
+            format!("XRI  {:#x}", data)
         }
         Instruction::Ori { data } => {
-            print_opcodes(instruction, 2);
-            println!("ORI  {:#x}", data);
+            format!("ORI  {:#x}", data)
         }
         Instruction::Cpi { data } => {
-            print_opcodes(instruction, 2);
-            println!("CPI  {:#x}", data);
+            format!("CPI  {:#x}", data)
         }
         Instruction::Sta { exp } => {
-            print_opcodes(instruction, 3);
-            println!("STA  {:#x}", exp);
+            format!("STA  {:#x}", exp)
         }
         Instruction::Lda { exp } => {
-            print_opcodes(instruction, 3);
-            println!("LDA  {:#x}", exp);
+            format!("LDA  {:#x}", exp)
         }
         Instruction::Shld { exp } => {
-            print_opcodes(instruction, 3);
-            println!("SHLD {:#x}", exp);
+            format!("SHLD {:#x}", exp)
         }
         Instruction::Lhld { exp } => {
-            print_opcodes(instruction, 3);
-            println!("LHLD {:#x}", exp);
+            format!("LHLD {:#x}", exp)
         }
         Instruction::Pchl => {
-            print_opcodes(instruction, 1);
-            println!("PCHL");
+            "PCHL".to_string()
         }
         Instruction::Jmp { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JMP  {:#x}", exp);
+            format!("JMP  {:#x}", exp)
         }
         Instruction::Jc { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JC   {:#x}", exp);
+            format!("JC   {:#x}", exp)
         }
         Instruction::Jnc { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JNC  {:#x}", exp);
+            format!("JNC  {:#x}", exp)
         }
         Instruction::Jz { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JZ   {:#x}", exp);
+            format!("JZ   {:#x}", exp)
         }
         Instruction::Jnz { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JNZ  {:#x}", exp);
+            format!("JNZ  {:#x}", exp)
         }
         Instruction::Jm { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JM   {:#x}", exp);
+            format!("JM   {:#x}", exp)
         }
         Instruction::Jp { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JP   {:#x}", exp);
+            format!("JP   {:#x}", exp)
         }
         Instruction::Jpe { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JPE  {:#x}", exp);
+            format!("JPE  {:#x}", exp)
         }
         Instruction::Jpo { exp } => {
-            print_opcodes(instruction, 3);
-            println!("JPO  {:#x}", exp);
+            format!("JPO  {:#x}", exp)
         }
         Instruction::Call { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CALL {:#x}", sub);
+            format!("CALL {:#x}", sub)
         }
         Instruction::Cc { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CC   {:#x}", sub);
+            format!("CC   {:#x}", sub)
         }
         Instruction::Cnc { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CNC  {:#x}", sub);
+            format!("CNC  {:#x}", sub)
         }
         Instruction::Cz { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CZ   {:#x}", sub);
+            format!("CZ   {:#x}", sub)
         }
         Instruction::Cnz { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CNZ  {:#x}", sub);
+            format!("CNZ  {:#x}", sub)
         }
         Instruction::Cm { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CM   {:#x}", sub);
+            format!("CM   {:#x}", sub)
         }
         Instruction::Cp { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CP   {:#x}", sub);
+            format!("CP   {:#x}", sub)
         }
         Instruction::Cpe { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CPE  {:#x}", sub);
+            format!("CPE  {:#x}", sub)
         }
         Instruction::Cpo { sub } => {
-            print_opcodes(instruction, 3);
-            println!("CPO  {:#x}", sub);
+            format!("CPO  {:#x}", sub)
         }
         Instruction::Ret => {
-            print_opcodes(instruction, 1);
-            println!("RET");
+            "RET".to_string()
         }
         Instruction::Rc => {
-            print_opcodes(instruction, 1);
-            println!("RC");
+            "RC".to_string()
         }
         Instruction::Rnc => {
-            print_opcodes(instruction, 1);
-            println!("RNC");
+            "RNC".to_string()
         }
         Instruction::Rz => {
-            print_opcodes(instruction, 1);
-            println!("RZ");
+            "RZ".to_string()
         }
         Instruction::Rnz => {
-            print_opcodes(instruction, 1);
-            println!("RNZ");
+            "RNZ".to_string()
         }
         Instruction::Rm => {
-            print_opcodes(instruction, 1);
-            println!("RM");
+            "RM".to_string()
         }
         Instruction::Rp => {
-            print_opcodes(instruction, 1);
-            println!("RP");
+            "RP".to_string()
         }
         Instruction::Rpe => {
-            print_opcodes(instruction, 1);
-            println!("RPE");
+            "RPE".to_string()
         }
         Instruction::Rpo => {
-            print_opcodes(instruction, 1);
-            println!("RPO");
+            "RPO".to_string()
         }
         Instruction::Rst { exp } => {
-            print_opcodes(instruction, 1);
-            println!("RST  {}", exp);
+            format!("RST  {}", exp)
         }
         Instruction::Ei => {
-            print_opcodes(instruction, 1);
-            println!("EI");
+            "EI".to_string()
         }
         Instruction::Di => {
-            print_opcodes(instruction, 1);
-            println!("DI");
+            "DI".to_string()
         }
         Instruction::In  { exp } => {
-            print_opcodes(instruction, 2);
-            println!("IN   {:#x}", exp);
+            format!("IN   {:#x}", exp)
         }
         Instruction::Out { exp } => {
-            print_opcodes(instruction, 2);
-            println!("OUT  {:#x}", exp);
+            format!("OUT  {:#x}", exp)
         }
         Instruction::Hlt => {
-            print_opcodes(instruction, 1);
-            println!("HLT");
+            "HLT".to_string()
+        }
+        Instruction::Rim => {
+            "RIM".to_string()
+        }
+        Instruction::Sim => {
+            "SIM".to_string()
+        }
+        Instruction::Dsub => {
+            "DSUB".to_string()
+        }
+        Instruction::Arhl => {
+            "ARHL".to_string()
+        }
+        Instruction::Rdel => {
+            "RDEL".to_string()
+        }
+        Instruction::Ldhi { data } => {
+            format!("LDHI {:#x}", data)
+        }
+        Instruction::Ldsi { data } => {
+            format!("LDSI {:#x}", data)
+        }
+        Instruction::Rstv => {
+            "RSTV".to_string()
+        }
+        Instruction::Shlx => {
+            "SHLX".to_string()
         }
-    };
-    Ok(decoded_instruction)
+        Instruction::Lhlx => {
+            "LHLX".to_string()
+        }
+        Instruction::Jnk { exp } => {
+            format!("JNK  {:#x}", exp)
+        }
+        Instruction::Jk  { exp } => {
+            format!("JK   {:#x}", exp)
+        }
+    }
+}
+
+pub fn line(cpu: Cpu, instruction: &[u8]) -> Result<(Instruction, String), Box<dyn Error>> {
+    let decoded_instruction = decoder::decode(cpu, instruction)?;
+    let mut text            = opcodes(instruction, decoded_instruction.len().into());
+
+    text.push_str(&mnemonic(decoded_instruction));
+
+    if decoder::is_undocumented(cpu, instruction[0]) {
+        text.push_str("  ; undocumented");
+    }
+
+    Ok((decoded_instruction, text))
 }
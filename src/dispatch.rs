@@ -0,0 +1,119 @@
+use std::sync::LazyLock;
+
+use crate::cpu::Cpu;
+use crate::decoder;
+use crate::emulator::Emulator;
+use crate::instruction::Flow;
+
+pub type Handler = fn(&mut Emulator, [u8; 3]) -> bool;
+
+#[derive(Clone, Copy)]
+pub struct OpEntry {
+    pub handler:      Handler,
+    pub length:       u8,
+    pub cycles:       u8,
+    pub taken_cycles: u8,
+    pub flow:         Flow,
+}
+
+pub static TABLE_8080: LazyLock<[OpEntry; 256]> = LazyLock::new(|| build(Cpu::I8080));
+pub static TABLE_8085: LazyLock<[OpEntry; 256]> = LazyLock::new(|| build(Cpu::I8085));
+
+pub fn table(cpu: Cpu) -> &'static [OpEntry; 256] {
+    match cpu {
+        Cpu::I8080 => &TABLE_8080,
+        Cpu::I8085 => &TABLE_8085,
+    }
+}
+
+fn build(cpu: Cpu) -> [OpEntry; 256] {
+    std::array::from_fn(|opcode| {
+        let instruction = decoder::decode(cpu, &[opcode as u8, 0, 0]).unwrap();
+        let handler     = match cpu {
+            Cpu::I8080 => handler(opcode as u8),
+            Cpu::I8085 => handler_8085(opcode as u8),
+        };
+
+        OpEntry {
+            handler,
+            length:       instruction.len(),
+            cycles:       instruction.cycles(cpu, false),
+            taken_cycles: instruction.cycles(cpu, true),
+            flow:         instruction.flow(),
+        }
+    })
+}
+
+fn handler_8085(opcode: u8) -> Handler {
+    match opcode {
+        0x08        => Emulator::dsub,
+        0x10        => Emulator::arhl,
+        0x18        => Emulator::rdel,
+        0x20        => Emulator::rim,
+        0x28        => Emulator::ldhi,
+        0x30        => Emulator::sim,
+        0x38        => Emulator::ldsi,
+        0xcb        => Emulator::rstv,
+        0xd9        => Emulator::shlx,
+        0xdd | 0xfd => Emulator::jmp_k,
+        0xed        => Emulator::lhlx,
+        _           => handler(opcode),
+    }
+}
+
+fn handler(opcode: u8) -> Handler {
+    match opcode {
+        0x00 | 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38
+                                                       => Emulator::nop,
+        0x01 | 0x11 | 0x21 | 0x31                      => Emulator::lxi,
+        0x02 | 0x12                                    => Emulator::stax,
+        0x03 | 0x13 | 0x23 | 0x33                      => Emulator::inx,
+        0x04 | 0x0c | 0x14 | 0x1c | 0x24 | 0x2c | 0x34 | 0x3c
+                                                       => Emulator::inr,
+        0x05 | 0x0d | 0x15 | 0x1d | 0x25 | 0x2d | 0x35 | 0x3d
+                                                       => Emulator::dcr,
+        0x06 | 0x0e | 0x16 | 0x1e | 0x26 | 0x2e | 0x36 | 0x3e
+                                                       => Emulator::mvi,
+        0x07                                           => Emulator::rlc,
+        0x0f                                           => Emulator::rrc,
+        0x17                                           => Emulator::ral,
+        0x1f                                           => Emulator::rar,
+        0x09 | 0x19 | 0x29 | 0x39                      => Emulator::dad,
+        0x0a | 0x1a                                    => Emulator::ldax,
+        0x0b | 0x1b | 0x2b | 0x3b                      => Emulator::dcx,
+        0x22                                           => Emulator::shld,
+        0x2a                                           => Emulator::lhld,
+        0x32                                           => Emulator::sta,
+        0x3a                                           => Emulator::lda,
+        0x27                                           => Emulator::daa,
+        0x2f                                           => Emulator::cma,
+        0x37                                           => Emulator::stc,
+        0x3f                                           => Emulator::cmc,
+        0x76                                           => Emulator::hlt,
+        0x40..=0x7f                                    => Emulator::mov,
+        0x80..=0xbf                                    => Emulator::alu,
+        0xc0 | 0xc8 | 0xd0 | 0xd8 | 0xe0 | 0xe8 | 0xf0 | 0xf8
+                                                       => Emulator::ret_if,
+        0xc1 | 0xd1 | 0xe1 | 0xf1                      => Emulator::pop,
+        0xc2 | 0xca | 0xd2 | 0xda | 0xe2 | 0xea | 0xf2 | 0xfa
+                                                       => Emulator::jmp_if,
+        0xc3 | 0xcb                                    => Emulator::jmp,
+        0xc4 | 0xcc | 0xd4 | 0xdc | 0xe4 | 0xec | 0xf4 | 0xfc
+                                                       => Emulator::call_if,
+        0xc5 | 0xd5 | 0xe5 | 0xf5                      => Emulator::push,
+        0xc6 | 0xce | 0xd6 | 0xde | 0xe6 | 0xee | 0xf6 | 0xfe
+                                                       => Emulator::alu_immediate,
+        0xc7 | 0xcf | 0xd7 | 0xdf | 0xe7 | 0xef | 0xf7 | 0xff
+                                                       => Emulator::rst,
+        0xc9 | 0xd9                                    => Emulator::ret,
+        0xcd | 0xdd | 0xed | 0xfd                      => Emulator::call,
+        0xd3                                           => Emulator::out,
+        0xdb                                           => Emulator::input,
+        0xe3                                           => Emulator::xthl,
+        0xe9                                           => Emulator::pchl,
+        0xeb                                           => Emulator::xchg,
+        0xf3                                           => Emulator::di,
+        0xf9                                           => Emulator::sphl,
+        0xfb                                           => Emulator::ei,
+    }
+}
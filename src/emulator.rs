@@ -1,934 +1,2183 @@
+use std::collections::BTreeSet;
 use std::error::Error;
 
+use crate::call_stack::{CallFrame, CallKind, CallStack};
+use crate::cheats::Cheats;
+use crate::coverage::{Access, Coverage};
+use crate::cpu::Cpu;
 use crate::cpu_state::CpuState;
+use crate::decoder;
 use crate::disassembler;
-use crate::instruction::Instruction;
-use crate::interrupt_timer::InterruptTimers;
-use crate::io_ports::IOPorts;
+use crate::dispatch::{self, OpEntry};
+use crate::instruction::{Flow, Register, RegisterPair};
+use crate::interrupt_controller::{InterruptController, InterruptSource};
+use crate::interrupt_lines::{InterruptLine, InterruptLines};
+use crate::io_ports::{Button, IOPorts};
 use crate::memory::Memory;
+use crate::patch;
+use crate::profiler::Profiler;
+use crate::registers::Registers;
+use crate::scheduler::{DeviceId, Event, Scheduler, Timing};
+use crate::snapshot::{Reader, Writer};
+use crate::statistics::Statistics;
+use crate::symbols::Symbols;
+use crate::video::Video;
+use crate::watch::{WatchEvent, Watches};
+use crate::watchdog::Watchdog;
 
+const HALT_CYCLES:   u8 = 4;
+const VECTOR_CYCLES: u8 = 12;
+
+const WATCHDOG: DeviceId = 0;
+
+const STATE_HEADER: &[u8] = b"space_invaders state 1";
+
+// Receives each traced instruction as an address and a disassembly line.
+// The emulator itself never writes to stdout.
+pub type Tracer = fn(u16, &str);
+
+// Runs when a device's deadline comes due, with the cycle it was due at, and
+// returns the cycle it wants to run at next.
+pub type Device = fn(&mut Emulator, u64) -> Option<u64>;
+
+#[derive(Clone)]
 pub struct Emulator {
+    cpu:              Cpu,
+    table:            &'static [OpEntry; 256],
     cpu_state:        CpuState,
     memory:           Memory,
     io_ports:         IOPorts,
-    interrupt_timers: InterruptTimers,
+    scheduler:        Scheduler,
+    timing:           Timing,
+    frame:            u64,
+    frame_origin:     u64,
+    devices:          Vec<Device>,
+    watchdog:         Watchdog,
+    interrupts:       InterruptController,
+    lines:            InterruptLines,
+    video:            Video,
+    statistics:       Statistics,
+    coverage:         Option<Coverage>,
+    profiler:         Option<Profiler>,
+    call_stack:       CallStack,
+    symbols:          Symbols,
+    breakpoints:      BTreeSet<u16>,
+    cheats:           Cheats,
+    watches:          Option<Watches>,
+    stopped_at:       Option<u16>,
+    tracer:           Option<Tracer>,
+    strict:           bool,
+}
+
+impl Emulator {
+    pub fn new() -> Emulator {
+        Emulator::with_timing(Timing::default())
+    }
+
+    pub fn with_timing(timing: Timing) -> Emulator {
+        Emulator::with_cpu(Cpu::I8080, timing)
+    }
+
+    pub fn with_cpu(cpu: Cpu, timing: Timing) -> Emulator {
+        let mut emulator = Emulator {
+            cpu,
+            table:            dispatch::table(cpu),
+            cpu_state:        CpuState::new(),
+            memory:           Memory::new(),
+            io_ports:         IOPorts::new(),
+            scheduler:        Scheduler::new(),
+            timing,
+            frame:            0,
+            frame_origin:     0,
+            devices:          vec![Emulator::watchdog_expired],
+            watchdog:         Watchdog::new(timing),
+            interrupts:       InterruptController::new(),
+            lines:            InterruptLines::new(),
+            video:            Video::new(),
+            statistics:       Statistics::new(),
+            coverage:         None,
+            profiler:         None,
+            call_stack:       CallStack::new(),
+            symbols:          Symbols::new(),
+            breakpoints:      BTreeSet::new(),
+            cheats:           Cheats::new(),
+            watches:          None,
+            stopped_at:       None,
+            tracer:           None,
+            strict:           false,
+        };
+
+        emulator.schedule_frame();
+        emulator.arm_watchdog();
+        emulator
+    }
+
+    pub fn cpu(&self) -> Cpu {
+        self.cpu
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.scheduler.cycles()
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            pc:   self.cpu_state.pc.get(),
+            sp:   self.cpu_state.sp.get(),
+            a:    self.cpu_state.psw.a,
+            f:    self.cpu_state.psw.flags(),
+            b:    self.cpu_state.bc.rh,
+            c:    self.cpu_state.bc.rl,
+            d:    self.cpu_state.de.rh,
+            e:    self.cpu_state.de.rl,
+            h:    self.cpu_state.hl.rh,
+            l:    self.cpu_state.hl.rl,
+            inte: self.cpu_state.inte,
+        }
+    }
+
+    pub fn set_registers(&mut self, registers: &Registers) {
+        self.cpu_state.pc.set(registers.pc);
+        self.cpu_state.sp.set(registers.sp);
+        self.cpu_state.psw.a = registers.a;
+        self.cpu_state.psw.set_flags(registers.f);
+        self.cpu_state.bc.rh = registers.b;
+        self.cpu_state.bc.rl = registers.c;
+        self.cpu_state.de.rh = registers.d;
+        self.cpu_state.de.rl = registers.e;
+        self.cpu_state.hl.rh = registers.h;
+        self.cpu_state.hl.rl = registers.l;
+        self.cpu_state.inte  = registers.inte;
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub fn io_ports(&self) -> &IOPorts {
+        &self.io_ports
+    }
+
+    pub fn io_ports_mut(&mut self) -> &mut IOPorts {
+        &mut self.io_ports
+    }
+
+    pub fn video(&self) -> &Video {
+        &self.video
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    pub fn enable_coverage(&mut self) {
+        self.coverage.get_or_insert_with(Coverage::new);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn enable_profiler(&mut self) {
+        self.profiler.get_or_insert_with(Profiler::new);
+    }
+
+    pub fn call_stack(&self) -> &CallStack {
+        &self.call_stack
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn backtrace(&self) -> String {
+        self.call_stack.backtrace(self.cpu_state.pc.get(), &self.symbols)
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u16) {
+        self.breakpoints.remove(&address);
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    // Replaces the current cheats, removing their patches first.
+    pub fn set_cheats(&mut self, mut cheats: Cheats) -> Result<(), Box<dyn Error>> {
+        self.cheats.uninstall(&mut self.memory)?;
+        cheats.install(&mut self.memory)?;
+        self.cheats = cheats;
+        Ok(())
+    }
+
+    pub fn set_cheat_enabled(&mut self, index: usize, enabled: bool) -> Result<(), Box<dyn Error>> {
+        self.cheats.set_enabled(index, enabled, &mut self.memory)
+    }
+
+    pub fn toggle_cheat(&mut self, index: usize) -> Result<bool, Box<dyn Error>> {
+        self.cheats.toggle(index, &mut self.memory)
+    }
+
+    pub fn watches_mut(&mut self) -> &mut Watches {
+        self.watches.get_or_insert_with(Watches::new)
+    }
+
+    pub fn take_watch_events(&mut self) -> Vec<WatchEvent> {
+        self.watches.as_mut().map_or_else(Vec::new, Watches::take_events)
+    }
+
+    // Like the reset line on the board, this restarts the CPU, the
+    // interrupt latch, the shift register and the video timing and re-arms
+    // the watchdog. Memory, the cycle count and other devices are kept.
+    pub fn reset(&mut self) {
+        self.cpu_state  = CpuState::new();
+        self.interrupts = InterruptController::new();
+        self.io_ports.reset();
+        self.call_stack.clear();
+
+        self.scheduler.cancel(Event::MidScreen);
+        self.scheduler.cancel(Event::VBlank);
+        self.frame        = 0;
+        self.frame_origin = self.scheduler.cycles();
+        self.schedule_frame();
+        self.arm_watchdog();
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.reset();
+        }
+    }
+
+    // A saved state holds the machine: CPU, memory, ports, interrupts,
+    // video and pending events. Debugging tools, cheats and devices stay as
+    // they are on the emulator that loads it.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state     = Writer::new();
+        let     registers = self.registers();
+
+        state.bytes(STATE_HEADER);
+        state.u8(match self.cpu {
+            Cpu::I8080 => 0,
+            Cpu::I8085 => 1,
+        });
+        state.u64(self.timing.clock_hz());
+        state.u64(self.timing.refresh_hz());
+
+        for value in [registers.pc, registers.sp] {
+            state.u16(value);
+        }
+        for value in [registers.a, registers.f, registers.b, registers.c,
+                      registers.d, registers.e, registers.h, registers.l] {
+            state.u8(value);
+        }
+        state.bool(registers.inte);
+        state.bool(self.cpu_state.halted);
+
+        state.bytes(&self.memory.memory);
+        self.io_ports.save(&mut state);
+        self.scheduler.save(&mut state);
+        state.u64(self.frame);
+        state.u64(self.frame_origin);
+        self.watchdog.save(&mut state);
+        self.interrupts.save(&mut state);
+        self.lines.save(&mut state);
+        self.video.save(&mut state);
+        state.finish()
+    }
+
+    // The state is read into a copy, so a bad one leaves the emulator as
+    // it was.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut state    = Reader::new(state);
+        let mut emulator = self.clone();
+
+        if state.bytes()? != STATE_HEADER {
+            return Err("not a saved state".into());
+        }
+        emulator.cpu = match state.u8()? {
+            0 => Cpu::I8080,
+            1 => Cpu::I8085,
+            _ => return Err("saved state has an unknown CPU".into()),
+        };
+        emulator.table  = dispatch::table(emulator.cpu);
+        emulator.timing = Timing::new(state.u64()?, state.u64()?)?;
+
+        let registers = Registers {
+            pc:   state.u16()?,
+            sp:   state.u16()?,
+            a:    state.u8()?,
+            f:    state.u8()?,
+            b:    state.u8()?,
+            c:    state.u8()?,
+            d:    state.u8()?,
+            e:    state.u8()?,
+            h:    state.u8()?,
+            l:    state.u8()?,
+            inte: state.bool()?,
+        };
+
+        emulator.set_registers(&registers);
+        emulator.cpu_state.halted = state.bool()?;
+
+        emulator.memory.memory = state.bytes()?.to_vec();
+        emulator.io_ports.load(&mut state)?;
+        emulator.scheduler.load(&mut state, emulator.devices.len())?;
+        emulator.frame        = state.u64()?;
+        emulator.frame_origin = state.u64()?;
+        emulator.watchdog.load(&mut state)?;
+        emulator.interrupts.load(&mut state)?;
+        emulator.lines.load(&mut state)?;
+        emulator.video.load(&mut state)?;
+        state.finish()?;
+
+        emulator.call_stack.clear();
+        if let Some(profiler) = &mut emulator.profiler {
+            profiler.reset();
+        }
+        *self = emulator;
+        Ok(())
+    }
+
+    pub fn set_watchdog(&mut self, enabled: bool) {
+        self.watchdog.set_enabled(enabled);
+        self.arm_watchdog();
+    }
+
+    pub fn add_device(&mut self, device: Device) -> DeviceId {
+        self.devices.push(device);
+        self.devices.len() - 1
+    }
+
+    // Replaces any deadline the device already has.
+    pub fn schedule_device(&mut self, id: DeviceId, at: u64) {
+        self.scheduler.cancel(Event::Device(id));
+        self.scheduler.schedule(at, Event::Device(id));
+    }
+
+    pub fn cancel_device(&mut self, id: DeviceId) {
+        self.scheduler.cancel(Event::Device(id));
+    }
+
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn raise_interrupt(&mut self, source: InterruptSource) {
+        self.interrupts.request(source);
+    }
+
+    pub fn set_interrupt_line(&mut self, line: InterruptLine, level: bool) {
+        self.lines.set(line, level);
+    }
+
+    pub fn set_sid(&mut self, level: bool) {
+        self.lines.set_sid(level);
+    }
+
+    pub fn sod(&self) -> bool {
+        self.lines.sod()
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.io_ports.set_button(button, pressed);
+    }
+
+    // Patches are applied in order to the image before it is loaded.
+    pub fn load_rom(&mut self, path: &str, patches: &[&str]) -> Result<(), Box<dyn Error>> {
+        let mut rom = std::fs::read(path)?;
+
+        for patch in patches {
+            rom = patch::apply(&rom, &std::fs::read(patch)?)
+                .map_err(|error| format!("{}: {}", patch, error))?;
+        }
+        if rom.len() > self.memory.len() {
+            return Err(format!("ROM of {} bytes does not fit in {} bytes of memory",
+                               rom.len(), self.memory.len()).into());
+        }
+        self.memory.write(0, &rom);
+        Ok(())
+    }
+
+    fn get_register(&mut self, reg: Register) -> u8 {
+        match reg {
+            Register::B => self.cpu_state.bc.rh,
+            Register::C => self.cpu_state.bc.rl,
+            Register::D => self.cpu_state.de.rh,
+            Register::E => self.cpu_state.de.rl,
+            Register::H => self.cpu_state.hl.rh,
+            Register::L => self.cpu_state.hl.rl,
+            Register::M => self.read8(self.cpu_state.hl.get()),
+            Register::A => self.cpu_state.psw.a,
+        }
+    }
+
+    fn set_register(&mut self, reg: Register, value: u8) {
+        match reg {
+            Register::B => self.cpu_state.bc.rh = value,
+            Register::C => self.cpu_state.bc.rl = value,
+            Register::D => self.cpu_state.de.rh = value,
+            Register::E => self.cpu_state.de.rl = value,
+            Register::H => self.cpu_state.hl.rh = value,
+            Register::L => self.cpu_state.hl.rl = value,
+            Register::M => self.write8(self.cpu_state.hl.get(), value),
+            Register::A => self.cpu_state.psw.a = value,
+        };
+    }
+
+    fn get_register_pair(&mut self, rp: RegisterPair) -> u16 {
+        match rp {
+            RegisterPair::B   => self.cpu_state.bc.get(),
+            RegisterPair::D   => self.cpu_state.de.get(),
+            RegisterPair::H   => self.cpu_state.hl.get(),
+            RegisterPair::Sp  => self.cpu_state.sp.get(),
+            RegisterPair::Psw => self.cpu_state.psw.get(),
+        }
+    }
+
+    fn set_register_pair(&mut self, rp: RegisterPair, value: u16) {
+        match rp {
+            RegisterPair::B   => self.cpu_state.bc.set(value),
+            RegisterPair::D   => self.cpu_state.de.set(value),
+            RegisterPair::H   => self.cpu_state.hl.set(value),
+            RegisterPair::Sp  => self.cpu_state.sp.set(value),
+            RegisterPair::Psw => self.cpu_state.psw.set(self.psw_bits(value)),
+        }
+    }
+
+    // The 8080 always reads bit 1 of the flags as set and bits 3 and 5 as
+    // clear. The 8085 keeps V and K in bits 1 and 5.
+    fn psw_bits(&self, value: u16) -> u16 {
+        match self.cpu {
+            Cpu::I8080 => value & !0x0028 | 0x0002,
+            Cpu::I8085 => value & !0x0008,
+        }
+    }
+
+    fn read8(&mut self, address: u16) -> u8 {
+        let value = self.memory.read8(address);
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address, Access::Read);
+        }
+        if let Some(watches) = &mut self.watches {
+            watches.read(address, value);
+        }
+        value
+    }
+
+    fn read16(&mut self, address: u16) -> u16 {
+        let value = self.memory.read16(address);
+
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address, Access::Read);
+            coverage.record(address.wrapping_add(1), Access::Read);
+        }
+        if let Some(watches) = &mut self.watches {
+            let [low, high] = value.to_le_bytes();
+
+            watches.read(address, low);
+            watches.read(address.wrapping_add(1), high);
+        }
+        value
+    }
+
+    fn write8(&mut self, address: u16, data: u8) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address, Access::Write);
+        }
+        if let Some(watches) = &mut self.watches {
+            watches.write(address, data);
+        }
+        self.memory.write8(address, data);
+    }
+
+    fn write16(&mut self, address: u16, data: u16) {
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address, Access::Write);
+            coverage.record(address.wrapping_add(1), Access::Write);
+        }
+        if let Some(watches) = &mut self.watches {
+            let [low, high] = data.to_le_bytes();
+
+            watches.write(address, low);
+            watches.write(address.wrapping_add(1), high);
+        }
+        self.memory.write16(address, data);
+    }
+
+    fn read_sp(&mut self) -> u16 {
+        self.read16(self.cpu_state.sp.get())
+    }
+
+    fn write_sp(&mut self, data: u16) {
+        self.write16(self.cpu_state.sp.get(), data);
+    }
+
+    fn push_word(&mut self, data: u16) {
+        self.cpu_state.sp -= 2;
+        self.write_sp(data);
+    }
+
+    fn pop_word(&mut self) -> u16 {
+        let data = self.read_sp();
+
+        self.cpu_state.sp += 2;
+        data
+    }
+
+    fn condition(&self, code: u8) -> bool {
+        match code {
+            0b000 => !self.cpu_state.psw.is_zero_set(),
+            0b001 =>  self.cpu_state.psw.is_zero_set(),
+            0b010 => !self.cpu_state.psw.is_carry_set(),
+            0b011 =>  self.cpu_state.psw.is_carry_set(),
+            0b100 => !self.cpu_state.psw.is_parity_set(),
+            0b101 =>  self.cpu_state.psw.is_parity_set(),
+            0b110 => !self.cpu_state.psw.is_sign_set(),
+            0b111 =>  self.cpu_state.psw.is_sign_set(),
+            _     => unreachable!(),
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        loop {
+            self.step()?;
+        }
+    }
+
+    pub fn run_frame(&mut self) -> Result<(), Box<dyn Error>> {
+        let frames = self.video.frames();
+
+        while self.video.frames() == frames {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn step(&mut self) -> Result<u8, Box<dyn Error>> {
+        let program_counter = self.cpu_state.pc.get();
+        let mut instruction = [0u8; 3];
+        let mut interrupted = false;
+
+        // Stepping again after a breakpoint stop runs the instruction.
+        if !self.breakpoints.is_empty() && !self.cpu_state.halted {
+            if self.breakpoints.contains(&program_counter)
+                && self.stopped_at != Some(program_counter) {
+                self.stopped_at = Some(program_counter);
+                return Err(format!("breakpoint at {:04x}", program_counter).into());
+            }
+            self.stopped_at = None;
+        }
+
+        self.memory.read(program_counter.into(), &mut instruction);
+
+        // Checked before any interrupt is acknowledged so the error leaves
+        // the interrupt state untouched.
+        if self.strict && !self.cpu_state.halted
+            && decoder::is_undocumented(self.cpu, instruction[0]) {
+            return Err(format!("undocumented opcode {:02x} at {:04x}",
+                               instruction[0], program_counter).into());
+        }
+
+        let ready = self.interrupts.ready(self.cpu_state.inte);
+
+        if self.cpu == Cpu::I8085 {
+            if let Some(vector) = self.lines.acknowledge(ready, self.cpu_state.inte) {
+                self.cpu_state.inte   = false;
+                self.cpu_state.halted = false;
+                self.push_word(program_counter);
+                self.cpu_state.pc.set(vector);
+                self.call_stack.call(CallFrame {
+                    kind:           CallKind::Interrupt,
+                    from:           program_counter,
+                    target:         vector,
+                    return_address: program_counter,
+                    return_sp:      self.cpu_state.sp.get(),
+                });
+
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.record(VECTOR_CYCLES);
+                    profiler.follow(self.call_stack.frames());
+                }
+                self.tick(VECTOR_CYCLES);
+
+                return Ok(VECTOR_CYCLES);
+            }
+        }
+
+        let source = if ready { self.interrupts.acknowledge() } else { None };
+
+        if let Some(source) = source {
+            instruction = source.bytes();
+            self.cpu_state.inte   = false;
+            self.cpu_state.halted = false;
+            interrupted = true;
+        }
+
+        // A halted CPU stops fetching but the clock keeps running until an
+        // interrupt is accepted. Only TRAP can wake an 8085 with interrupts
+        // disabled.
+        if self.cpu_state.halted {
+            if !self.cpu_state.inte && self.cpu == Cpu::I8080 {
+                return Err(format!("deadlock: halted at {:04x} with interrupts disabled",
+                                   program_counter.wrapping_sub(1)).into());
+            }
+            if let Some(profiler) = &mut self.profiler {
+                profiler.record(HALT_CYCLES);
+            }
+            self.tick(HALT_CYCLES);
+            return Ok(HALT_CYCLES);
+        }
+
+        if let Some(tracer) = self.tracer {
+            tracer(program_counter, &disassembler::line(self.cpu, &instruction)?.1);
+        }
+
+        let entry         = &self.table[usize::from(instruction[0])];
+        let stack_pointer = self.cpu_state.sp.get();
+
+        if !interrupted {
+            self.cpu_state.pc += u16::from(entry.length);
+
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record(program_counter, Access::Opcode);
+                for offset in 1..u16::from(entry.length) {
+                    coverage.record(program_counter.wrapping_add(offset), Access::Operand);
+                }
+            }
+        }
+
+        let cycles = if (entry.handler)(self, instruction) {
+            entry.taken_cycles
+        } else {
+            entry.cycles
+        };
+
+        self.statistics.record(instruction[0], cycles);
+
+        if entry.flow != Flow::Sequential {
+            self.track_stack(entry.flow, program_counter, stack_pointer, interrupted, instruction[0]);
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(cycles);
+            if entry.flow != Flow::Sequential {
+                profiler.follow(self.call_stack.frames());
+            }
+        }
+        self.tick(cycles);
+
+        Ok(cycles)
+    }
+
+    // A call that was not taken leaves the stack pointer where it was, and
+    // so does a return.
+    fn track_stack(&mut self, flow: Flow, from: u16, stack_pointer: u16, interrupted: bool,
+                   opcode: u8) {
+        let sp = self.cpu_state.sp.get();
+        let pc = self.cpu_state.pc.get();
+
+        match flow {
+            Flow::Call if sp == stack_pointer.wrapping_sub(2) => {
+                let kind = if interrupted {
+                    CallKind::Interrupt
+                } else if opcode & 0xc7 == 0xc7 || opcode == 0xcb {
+                    CallKind::Rst
+                } else {
+                    CallKind::Call
+                };
+
+                self.call_stack.call(CallFrame {
+                    kind,
+                    from,
+                    target:         pc,
+                    return_address: self.memory.read16(sp),
+                    return_sp:      sp,
+                });
+            }
+            Flow::Return if sp == stack_pointer.wrapping_add(2) => {
+                self.call_stack.ret(from, stack_pointer, pc);
+            }
+            Flow::Push     => self.call_stack.push(from, sp),
+            Flow::Pop      => self.call_stack.pop(from, sp),
+            Flow::Exchange => self.call_stack.exchange(from, sp, self.memory.read16(sp)),
+            Flow::LoadSp   => self.call_stack.load_sp(from, sp),
+            _              => {}
+        }
+    }
+
+    fn schedule_frame(&mut self) {
+        let origin = self.frame_origin;
+
+        self.scheduler.schedule(origin + self.timing.mid_screen(self.frame), Event::MidScreen);
+        self.scheduler.schedule(origin + self.timing.vblank(self.frame),     Event::VBlank);
+    }
+
+    // Interrupts are raised on the exact cycle their event is due and are
+    // latched until the CPU acknowledges them at an instruction boundary.
+    fn tick(&mut self, cycles: u8) {
+        self.scheduler.advance(u64::from(cycles));
+
+        while let Some((due, event)) = self.scheduler.pop_due() {
+            match event {
+                Event::MidScreen => {
+                    self.video.interrupt(1, &self.memory);
+                    self.interrupts.request(InterruptSource::Rst(1));
+                }
+                Event::VBlank => {
+                    self.video.interrupt(2, &self.memory);
+                    self.cheats.apply(&mut self.memory);
+                    self.frame += 1;
+
+                    if let Some(profiler) = &mut self.profiler {
+                        profiler.end_frame();
+                    }
+                    self.schedule_frame();
+                    self.interrupts.request(InterruptSource::Rst(2));
+                }
+                Event::Device(id) => {
+                    if let Some(at) = (self.devices[id])(self, due) {
+                        self.scheduler.schedule(at, Event::Device(id));
+                    }
+                }
+            }
+        }
+    }
+
+    fn arm_watchdog(&mut self) {
+        self.watchdog.kick(self.scheduler.cycles());
+
+        if self.watchdog.is_enabled() {
+            self.schedule_device(WATCHDOG, self.watchdog.deadline());
+        } else {
+            self.cancel_device(WATCHDOG);
+        }
+    }
+
+    // Kicks only record the time, so an expired deadline re-arms itself if
+    // the watchdog was kicked since it was scheduled.
+    fn watchdog_expired(&mut self, _: u64) -> Option<u64> {
+        let deadline = self.watchdog.deadline();
+
+        if deadline > self.scheduler.cycles() {
+            return Some(deadline);
+        }
+        self.reset();
+        None
+    }
+
+    pub(crate) fn nop(&mut self, _: [u8; 3]) -> bool {
+        false
+    }
+
+    pub(crate) fn hlt(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.halted = true;
+        false
+    }
+
+    pub(crate) fn stc(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.psw.set_carry(1);
+        false
+    }
+
+    pub(crate) fn cmc(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.psw.set_carry(u8::from(!self.cpu_state.psw.is_carry_set()));
+        false
+    }
+
+    pub(crate) fn inr(&mut self, instruction: [u8; 3]) -> bool {
+        let reg      = Register::from(instruction[0] >> 3);
+        let register = self.get_register(reg).wrapping_add(1);
+
+        self.set_register(reg, register);
+
+        self.cpu_state.psw.set_aux_carry(u8::from(register & 0xf == 0));
+        self.cpu_state.psw.set_parity(register);
+        self.cpu_state.psw.set_zero(register);
+        self.cpu_state.psw.set_sign(register);
+        false
+    }
+
+    pub(crate) fn dcr(&mut self, instruction: [u8; 3]) -> bool {
+        let reg      = Register::from(instruction[0] >> 3);
+        let register = self.get_register(reg).wrapping_sub(1);
+
+        self.set_register(reg, register);
+
+        self.cpu_state.psw.set_aux_carry(u8::from(register & 0xf != 0xf));
+        self.cpu_state.psw.set_parity(register);
+        self.cpu_state.psw.set_zero(register);
+        self.cpu_state.psw.set_sign(register);
+        false
+    }
+
+    pub(crate) fn cma(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.psw.a = !self.cpu_state.psw.a;
+        false
+    }
+
+    pub(crate) fn daa(&mut self, _: [u8; 3]) -> bool {
+        let acc    = self.cpu_state.psw.a;
+        let mut cy = self.cpu_state.psw.is_carry_set();
+        let mut correction = 0;
+
+        if self.cpu_state.psw.is_aux_carry_set() || acc & 0xf > 9 {
+            correction |= 0x06;
+        }
+        if cy || acc >> 4 > 9 || (acc >> 4 >= 9 && acc & 0xf > 9) {
+            correction |= 0x60;
+            cy = true;
+        }
+
+        let ac  = add_aux_carry(acc, correction, false);
+        let acc = acc.wrapping_add(correction);
+
+        self.cpu_state.psw.a = acc;
+
+        self.cpu_state.psw.set_carry(u8::from(cy));
+        self.cpu_state.psw.set_aux_carry(ac);
+        self.cpu_state.psw.set_parity(acc);
+        self.cpu_state.psw.set_zero(acc);
+        self.cpu_state.psw.set_sign(acc);
+        false
+    }
+
+    pub(crate) fn mov(&mut self, instruction: [u8; 3]) -> bool {
+        let dst = Register::from(instruction[0] >> 3);
+        let src = Register::from(instruction[0]);
+
+        let register = self.get_register(src);
+
+        self.set_register(dst, register);
+        false
+    }
+
+    pub(crate) fn stax(&mut self, instruction: [u8; 3]) -> bool {
+        let rp            = RegisterPair::from((instruction[0] >> 4) & 0b1);
+        let register_pair = self.get_register_pair(rp);
+
+        self.write8(register_pair, self.cpu_state.psw.a);
+        false
+    }
+
+    pub(crate) fn ldax(&mut self, instruction: [u8; 3]) -> bool {
+        let rp            = RegisterPair::from((instruction[0] >> 4) & 0b1);
+        let register_pair = self.get_register_pair(rp);
+
+        self.cpu_state.psw.a = self.read8(register_pair);
+        false
+    }
+
+    fn arithmetic(&mut self, op: u8, data: u8) {
+        let acc   = self.cpu_state.psw.a;
+        let carry = self.cpu_state.psw.is_carry_set();
+
+        let (result, cy, ac) = match op {
+            0b000 => {
+                let (result, cy) = acc.overflowing_add(data);
+                (result, cy, add_aux_carry(acc, data, false))
+            }
+            0b001 => {
+                let (result, cy) = acc.carrying_add(data, carry);
+                (result, cy, add_aux_carry(acc, data, carry))
+            }
+            0b010 | 0b111 => {
+                let (result, cy) = acc.overflowing_sub(data);
+                (result, cy, sub_aux_carry(acc, data, false))
+            }
+            0b011 => {
+                let (result, cy) = acc.borrowing_sub(data, carry);
+                (result, cy, sub_aux_carry(acc, data, carry))
+            }
+            0b100 if self.cpu == Cpu::I8085 => (acc & data, false, 1),
+            0b100 => (acc & data, false, (acc | data) & 0x08),
+            0b101 => (acc ^ data, false, 0),
+            0b110 => (acc | data, false, 0),
+            _     => unreachable!(),
+        };
+
+        if op != 0b111 {
+            self.cpu_state.psw.a = result;
+        }
+
+        self.cpu_state.psw.set_carry(u8::from(cy));
+        self.cpu_state.psw.set_aux_carry(ac);
+        self.cpu_state.psw.set_parity(result);
+        self.cpu_state.psw.set_zero(result);
+        self.cpu_state.psw.set_sign(result);
+
+        if self.cpu == Cpu::I8085 {
+            let overflow = match op {
+                0b000 | 0b001         => (acc ^ result) & (data ^ result) & 0x80,
+                0b010 | 0b011 | 0b111 => (acc ^ data) & (acc ^ result) & 0x80,
+                _                     => 0,
+            };
+
+            self.set_overflow_flags(overflow != 0, result & 0x80 != 0);
+        }
+    }
+
+    // On the 8085 V flags a signed overflow and K is S xor V, so after a
+    // subtraction K is set when the signed result went below the operand.
+    fn set_overflow_flags(&mut self, overflow: bool, sign: bool) {
+        self.cpu_state.psw.set_overflow(u8::from(overflow));
+        self.cpu_state.psw.set_underflow(u8::from(overflow != sign));
+    }
+
+    pub(crate) fn alu(&mut self, instruction: [u8; 3]) -> bool {
+        let op  = (instruction[0] >> 3) & 0b111;
+        let reg = Register::from(instruction[0]);
+
+        let register = self.get_register(reg);
+
+        self.arithmetic(op, register);
+        false
+    }
+
+    pub(crate) fn alu_immediate(&mut self, instruction: [u8; 3]) -> bool {
+        let op = (instruction[0] >> 3) & 0b111;
+
+        self.arithmetic(op, instruction[1]);
+        false
+    }
+
+    pub(crate) fn rlc(&mut self, _: [u8; 3]) -> bool {
+        let acc = self.cpu_state.psw.a;
+
+        let (acc, cy) = (acc.rotate_left(1), acc >> 7);
+
+        self.cpu_state.psw.a = acc;
+        self.cpu_state.psw.set_carry(cy);
+        false
+    }
+
+    pub(crate) fn rrc(&mut self, _: [u8; 3]) -> bool {
+        let acc = self.cpu_state.psw.a;
+
+        let (acc, cy) = (acc.rotate_right(1), acc & 1);
+
+        self.cpu_state.psw.a = acc;
+        self.cpu_state.psw.set_carry(cy);
+        false
+    }
+
+    pub(crate) fn ral(&mut self, _: [u8; 3]) -> bool {
+        let mut acc = self.cpu_state.psw.a;
+        let mut cy  = self.cpu_state.psw.get_carry();
+
+        (acc, cy) = ((acc << 1) | cy , acc & 0x80);
+
+        self.cpu_state.psw.a = acc;
+        self.cpu_state.psw.set_carry(cy);
+        false
+    }
+
+    pub(crate) fn rar(&mut self, _: [u8; 3]) -> bool {
+        let mut acc = self.cpu_state.psw.a;
+        let mut cy  = self.cpu_state.psw.get_carry();
+
+        (acc, cy) = ((acc >> 1) | (cy << 7), acc & 1);
+
+        self.cpu_state.psw.a = acc;
+        self.cpu_state.psw.set_carry(cy);
+        false
+    }
+
+    pub(crate) fn push(&mut self, instruction: [u8; 3]) -> bool {
+        let mut rp = (instruction[0] >> 4) & 0b11;
+
+        if rp == 0b11 { rp += 1; }
+
+        let register_pair = self.get_register_pair(RegisterPair::from(rp));
+
+        self.push_word(register_pair);
+        false
+    }
+
+    pub(crate) fn pop(&mut self, instruction: [u8; 3]) -> bool {
+        let mut rp = (instruction[0] >> 4) & 0b11;
+
+        if rp == 0b11 { rp += 1; }
+
+        let register_pair = self.pop_word();
+
+        self.set_register_pair(RegisterPair::from(rp), register_pair);
+        false
+    }
+
+    pub(crate) fn dad(&mut self, instruction: [u8; 3]) -> bool {
+        let rp            = RegisterPair::from((instruction[0] >> 4) & 0b11);
+        let register_pair = self.get_register_pair(rp);
+
+        let (hl, cy) = self.cpu_state.hl.get().overflowing_add(register_pair);
+
+        self.cpu_state.hl.set(hl);
+        self.cpu_state.psw.set_carry(u8::from(cy));
+        false
+    }
+
+    pub(crate) fn inx(&mut self, instruction: [u8; 3]) -> bool {
+        let rp            = RegisterPair::from((instruction[0] >> 4) & 0b11);
+        let register_pair = self.get_register_pair(rp).wrapping_add(1);
+
+        self.set_register_pair(rp, register_pair);
+
+        if self.cpu == Cpu::I8085 {
+            self.cpu_state.psw.set_underflow(u8::from(register_pair == 0x0000));
+        }
+        false
+    }
+
+    pub(crate) fn dcx(&mut self, instruction: [u8; 3]) -> bool {
+        let rp            = RegisterPair::from((instruction[0] >> 4) & 0b11);
+        let register_pair = self.get_register_pair(rp).wrapping_sub(1);
+
+        self.set_register_pair(rp, register_pair);
+
+        if self.cpu == Cpu::I8085 {
+            self.cpu_state.psw.set_underflow(u8::from(register_pair == 0xffff));
+        }
+        false
+    }
+
+    pub(crate) fn xchg(&mut self, _: [u8; 3]) -> bool {
+        std::mem::swap(&mut self.cpu_state.de, &mut self.cpu_state.hl);
+        false
+    }
+
+    pub(crate) fn xthl(&mut self, _: [u8; 3]) -> bool {
+        let tmp = self.read_sp();
+
+        self.write_sp(self.cpu_state.hl.get());
+        self.cpu_state.hl.set(tmp);
+        false
+    }
+
+    pub(crate) fn sphl(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.sp.set(self.cpu_state.hl.get());
+        false
+    }
+
+    pub(crate) fn lxi(&mut self, instruction: [u8; 3]) -> bool {
+        let rp   = RegisterPair::from((instruction[0] >> 4) & 0b11);
+        let data = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.set_register_pair(rp, data);
+        false
+    }
+
+    pub(crate) fn mvi(&mut self, instruction: [u8; 3]) -> bool {
+        let reg = Register::from(instruction[0] >> 3);
+
+        self.set_register(reg, instruction[1]);
+        false
+    }
+
+    pub(crate) fn sta(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.write8(exp, self.cpu_state.psw.a);
+        false
+    }
+
+    pub(crate) fn lda(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.cpu_state.psw.a = self.read8(exp);
+        false
+    }
+
+    pub(crate) fn shld(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.write16(exp, self.cpu_state.hl.get());
+        false
+    }
+
+    pub(crate) fn lhld(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        let data = self.read16(exp);
+
+        self.cpu_state.hl.set(data);
+        false
+    }
+
+    pub(crate) fn pchl(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.pc.set(self.cpu_state.hl.get());
+        false
+    }
+
+    pub(crate) fn jmp(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.cpu_state.pc.set(exp);
+        false
+    }
+
+    pub(crate) fn jmp_if(&mut self, instruction: [u8; 3]) -> bool {
+        if self.condition((instruction[0] >> 3) & 0b111) {
+            self.jmp(instruction);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn call(&mut self, instruction: [u8; 3]) -> bool {
+        let sub = u16::from_le_bytes([instruction[1], instruction[2]]);
+
+        self.push_word(self.cpu_state.pc.get());
+        self.cpu_state.pc.set(sub);
+        false
+    }
+
+    pub(crate) fn call_if(&mut self, instruction: [u8; 3]) -> bool {
+        if self.condition((instruction[0] >> 3) & 0b111) {
+            self.call(instruction);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn ret(&mut self, _: [u8; 3]) -> bool {
+        let address = self.pop_word();
+
+        self.cpu_state.pc.set(address);
+        false
+    }
+
+    pub(crate) fn ret_if(&mut self, instruction: [u8; 3]) -> bool {
+        if self.condition((instruction[0] >> 3) & 0b111) {
+            self.ret(instruction);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn rst(&mut self, instruction: [u8; 3]) -> bool {
+        let exp = (instruction[0] >> 3) & 0b111;
+
+        self.push_word(self.cpu_state.pc.get());
+        self.cpu_state.pc.set(u16::from(exp * 8));
+        false
+    }
+
+    pub(crate) fn ei(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.inte = true;
+        self.interrupts.delay();
+        false
+    }
+
+    pub(crate) fn di(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.inte = false;
+        false
+    }
+
+    pub(crate) fn input(&mut self, instruction: [u8; 3]) -> bool {
+        self.cpu_state.psw.a = self.io_ports.read(instruction[1]);
+
+        if let Some(watches) = &mut self.watches {
+            watches.port_in(instruction[1], self.cpu_state.psw.a);
+        }
+        false
+    }
+
+    pub(crate) fn out(&mut self, instruction: [u8; 3]) -> bool {
+        if let Some(watches) = &mut self.watches {
+            watches.port_out(instruction[1], self.cpu_state.psw.a);
+        }
+        self.io_ports.write(instruction[1], self.cpu_state.psw.a);
+
+        if instruction[1] == 6 {
+            self.watchdog.kick(self.scheduler.cycles());
+        }
+        false
+    }
+
+    pub(crate) fn rim(&mut self, _: [u8; 3]) -> bool {
+        self.cpu_state.psw.a = self.lines.rim(self.cpu_state.inte);
+        false
+    }
+
+    pub(crate) fn sim(&mut self, _: [u8; 3]) -> bool {
+        self.lines.sim(self.cpu_state.psw.a);
+        false
+    }
+
+    pub(crate) fn dsub(&mut self, _: [u8; 3]) -> bool {
+        let hl = self.cpu_state.hl.get();
+        let bc = self.cpu_state.bc.get();
+
+        let (result, cy) = hl.overflowing_sub(bc);
+        let borrow       = (hl & 0xff) < (bc & 0xff);
+        let [_, high]    = result.to_le_bytes();
+
+        self.cpu_state.hl.set(result);
+        self.cpu_state.psw.set_carry(u8::from(cy));
+        self.cpu_state.psw.set_aux_carry(sub_aux_carry((hl >> 8) as u8, (bc >> 8) as u8, borrow));
+        self.cpu_state.psw.set_parity(high);
+        self.cpu_state.psw.set_zero_flag(result == 0);
+        self.cpu_state.psw.set_sign(high);
+        self.set_overflow_flags((hl ^ bc) & (hl ^ result) & 0x8000 != 0, high & 0x80 != 0);
+        false
+    }
+
+    pub(crate) fn arhl(&mut self, _: [u8; 3]) -> bool {
+        let hl = self.cpu_state.hl.get();
+
+        self.cpu_state.hl.set(((hl as i16) >> 1) as u16);
+        self.cpu_state.psw.set_carry((hl & 1) as u8);
+        false
+    }
+
+    pub(crate) fn rdel(&mut self, _: [u8; 3]) -> bool {
+        let de = self.cpu_state.de.get();
+        let cy = self.cpu_state.psw.get_carry();
+
+        self.cpu_state.de.set((de << 1) | u16::from(cy));
+        self.cpu_state.psw.set_carry((de >> 15) as u8);
+        self.cpu_state.psw.set_overflow(((de ^ (de << 1)) >> 15) as u8);
+        false
+    }
+
+    pub(crate) fn ldhi(&mut self, instruction: [u8; 3]) -> bool {
+        let hl = self.cpu_state.hl.get();
+
+        self.cpu_state.de.set(hl.wrapping_add(u16::from(instruction[1])));
+        false
+    }
+
+    pub(crate) fn ldsi(&mut self, instruction: [u8; 3]) -> bool {
+        let sp = self.cpu_state.sp.get();
+
+        self.cpu_state.de.set(sp.wrapping_add(u16::from(instruction[1])));
+        false
+    }
+
+    pub(crate) fn rstv(&mut self, _: [u8; 3]) -> bool {
+        if self.cpu_state.psw.is_overflow_set() {
+            self.push_word(self.cpu_state.pc.get());
+            self.cpu_state.pc.set(0x40);
+            return true;
+        }
+        false
+    }
+
+    pub(crate) fn shlx(&mut self, _: [u8; 3]) -> bool {
+        self.write16(self.cpu_state.de.get(), self.cpu_state.hl.get());
+        false
+    }
+
+    pub(crate) fn lhlx(&mut self, _: [u8; 3]) -> bool {
+        let data = self.read16(self.cpu_state.de.get());
+
+        self.cpu_state.hl.set(data);
+        false
+    }
+
+    pub(crate) fn jmp_k(&mut self, instruction: [u8; 3]) -> bool {
+        let k = self.cpu_state.psw.is_underflow_set();
+
+        if k == (instruction[0] == 0xfd) {
+            self.jmp(instruction);
+            return true;
+        }
+        false
+    }
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn add_aux_carry(a: u8, b: u8, carry: bool) -> u8 {
+    u8::from((a & 0xf) + (b & 0xf) + u8::from(carry) > 0xf)
 }
 
-impl Emulator {
-    pub fn new() -> Emulator {
-        Emulator {
-            cpu_state:        CpuState::new(),
-            memory:           Memory::new(),
-            io_ports:         IOPorts::new(),
-            interrupt_timers: InterruptTimers::new(),
+fn sub_aux_carry(a: u8, b: u8, borrow: bool) -> u8 {
+    add_aux_carry(a, !b, !borrow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::call_stack::{Anomaly, StackAnomaly};
+
+    const LXI_SP: [u8; 3] = [0x31, 0x00, 0x24];
+
+    fn emulator(program: &[u8]) -> Emulator {
+        let mut emulator = Emulator::new();
+
+        emulator.memory_mut().write(0, &LXI_SP);
+        emulator.memory_mut().write(LXI_SP.len(), program);
+        emulator.step().unwrap();
+        emulator
+    }
+
+    #[test]
+    fn ei_delays_acceptance_by_one_instruction() {
+        let mut emulator = emulator(&[0xfb, 0x00, 0x00]);
+
+        emulator.raise_interrupt(InterruptSource::Rst(1));
+
+        let mut cycles = emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x04);
+
+        cycles += emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x05);
+        assert_eq!(cycles, 8);
+
+        assert_eq!(emulator.step().unwrap(), 11);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x08);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x05);
+    }
+
+    #[test]
+    fn pending_interrupt_is_latched_until_ei() {
+        let mut emulator = emulator(&[0x00, 0x00, 0x00, 0xfb, 0x00, 0x00]);
+
+        emulator.raise_interrupt(InterruptSource::Rst(2));
+
+        for pc in 0x04..=0x07 {
+            emulator.step().unwrap();
+            assert_eq!(emulator.cpu_state.pc.get(), pc);
         }
+
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x08);
+
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x10);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x08);
     }
 
-    pub fn load_rom(&mut self, path: &str) -> Result<(), Box<dyn Error>> {
-        self.memory.write(0, std::fs::read(path)?.as_slice());
-        Ok(())
+    #[test]
+    fn timer_interrupt_is_accepted_at_the_next_instruction_boundary() {
+        let mut emulator = emulator(&[0xfb, 0xc3, 0x04, 0x00]);
+        let mut cycles   = 10;
+
+        loop {
+            let step = u64::from(emulator.step().unwrap());
+
+            if emulator.cpu_state.pc.get() == 0x08 {
+                break;
+            }
+            cycles += step;
+        }
+
+        // RST 1 is due 16666 cycles after reset. LXI and EI take 14 cycles
+        // and each JMP 10, so the JMP in flight then completes at 16674.
+        assert_eq!(cycles, 16674);
     }
 
-    fn get_register(&self, reg: u8) -> u8 {
-        match reg {
-            0b000 => self.cpu_state.bc.rh,
-            0b001 => self.cpu_state.bc.rl,
-            0b010 => self.cpu_state.de.rh,
-            0b011 => self.cpu_state.de.rl,
-            0b100 => self.cpu_state.hl.rh,
-            0b101 => self.cpu_state.hl.rl,
-            0b110 => self.memory.read8(self.cpu_state.hl.get()),
-            0b111 => self.cpu_state.psw.a,
-            _     => unreachable!(),
+    #[test]
+    fn halt_wakes_on_the_next_interrupt() {
+        let mut emulator = emulator(&[0xfb, 0x76]);
+
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        for _ in 0..3 {
+            assert_eq!(emulator.step().unwrap(), HALT_CYCLES);
+            assert_eq!(emulator.cpu_state.pc.get(), 0x05);
         }
+
+        emulator.raise_interrupt(InterruptSource::Rst(1));
+        emulator.step().unwrap();
+
+        assert!(!emulator.cpu_state.halted);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x08);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x05);
     }
 
-    fn set_register(&mut self, reg: u8, value: u8) {
-        match reg {
-            0b000 => self.cpu_state.bc.rh = value,
-            0b001 => self.cpu_state.bc.rl = value,
-            0b010 => self.cpu_state.de.rh = value,
-            0b011 => self.cpu_state.de.rl = value,
-            0b100 => self.cpu_state.hl.rh = value,
-            0b101 => self.cpu_state.hl.rl = value,
-            0b110 => self.memory.write8(self.cpu_state.hl.get(), value),
-            0b111 => self.cpu_state.psw.a = value,
-            _     => unreachable!(),
-        };
+    #[test]
+    fn halt_with_interrupts_disabled_is_a_deadlock() {
+        let mut emulator = emulator(&[0xf3, 0x76]);
+
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        assert!(emulator.step().is_err());
     }
 
-    fn get_register_pair(&mut self, rp: u8) -> u16 {
-        match rp {
-            0b000 => self.cpu_state.bc.get(),
-            0b001 => self.cpu_state.de.get(),
-            0b010 => self.cpu_state.hl.get(),
-            0b011 => self.cpu_state.sp.get(),
-            0b100 => self.cpu_state.psw.get(),
-            _     => unreachable!(),
+    #[test]
+    fn raised_call_is_delivered_through_the_fetch_path() {
+        let mut emulator = emulator(&[0xfb, 0x00, 0x00]);
+
+        emulator.raise_interrupt(InterruptSource::Instruction([0xcd, 0x34, 0x12]));
+        emulator.raise_interrupt(InterruptSource::Rst(7));
+
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.step().unwrap(), 17);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x1234);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x05);
+        assert!(!emulator.cpu_state.inte);
+
+        emulator.cpu_state.inte = true;
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.cpu_state.pc.get(), 0x38);
+        assert_eq!(emulator.memory().read16(0x23fc), 0x1234);
+    }
+
+    #[test]
+    fn shadow_stack_follows_calls_and_returns() {
+        let mut emulator = emulator(&[0xcd, 0x10, 0x00]);
+
+        emulator.memory_mut().write(0x10, &[0xcd, 0x20, 0x00]);
+        emulator.memory_mut().write(0x20, &[0xc9]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        let backtrace = emulator.backtrace();
+        let lines: Vec<&str> = backtrace.lines().collect();
+
+        assert_eq!(lines, ["#0  0020", "#1  0010  (CALL 0020)", "#2  0003  (CALL 0010)"]);
+        assert_eq!(emulator.call_stack().frames()[1].return_address, 0x13);
+
+        emulator.step().unwrap();
+
+        assert_eq!(emulator.call_stack().frames().len(), 1);
+        assert_eq!(emulator.call_stack().anomaly_count(), 0);
+    }
+
+    #[test]
+    fn calls_drop_frames_left_behind_when_sp_is_moved_back() {
+        // CALL 0x0006, then INX SP twice and JMP 0x0003 to call again.
+        let mut emulator = emulator(&[0xcd, 0x06, 0x00, 0x33, 0x33, 0xc3, 0x03, 0x00]);
+
+        for _ in 0..4000 {
+            emulator.step().unwrap();
         }
+        assert_eq!(emulator.call_stack().frames().len(), 1);
     }
 
-    fn set_register_pair(&mut self, rp: u8, value: u16) {
-        match rp {
-            0b000 => self.cpu_state.bc.set(value),
-            0b001 => self.cpu_state.de.set(value),
-            0b010 => self.cpu_state.hl.set(value),
-            0b011 => self.cpu_state.sp.set(value),
-            0b100 => self.cpu_state.psw.set(value),
-            _     => unreachable!(),
+    #[test]
+    fn shadow_stack_depth_is_capped() {
+        // CALL 0x0003 recurses forever.
+        let mut emulator = emulator(&[0xcd, 0x03, 0x00]);
+
+        for _ in 0..2000 {
+            emulator.step().unwrap();
         }
+        assert_eq!(emulator.call_stack().frames().len(), 1024);
+        assert_eq!(emulator.call_stack().frames().last().unwrap().return_sp, 0x2400 - 2 * 2000);
     }
 
-    fn read_sp(&self) -> u16 {
-        self.memory.read16(self.cpu_state.sp.get())
+    #[test]
+    fn ret_to_an_address_never_pushed_is_flagged() {
+        // LXI H,0x0040; PUSH H; RET
+        let mut emulator = emulator(&[0x21, 0x40, 0x00, 0xe5, 0xc9]);
+
+        for _ in 0..3 {
+            emulator.step().unwrap();
+        }
+
+        let anomalies: Vec<&Anomaly> = emulator.call_stack().anomalies().collect();
+        assert_eq!(anomalies, [&Anomaly { pc: 0x07, kind: StackAnomaly::UnknownReturn { to: 0x40 } }]);
     }
 
-    fn write_sp(&mut self, data: u16) {
-        self.memory.write16(self.cpu_state.sp.get(), data);
+    #[test]
+    fn popping_a_return_address_is_flagged() {
+        // CALL 0x0010, then POP H discards the return address.
+        let mut emulator = emulator(&[0xcd, 0x10, 0x00]);
+
+        emulator.memory_mut().write(0x10, &[0xe1]);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+
+        let anomalies: Vec<&Anomaly> = emulator.call_stack().anomalies().collect();
+        assert_eq!(anomalies, [&Anomaly {
+            pc:   0x10,
+            kind: StackAnomaly::PoppedReturnAddress { return_address: 0x06 },
+        }]);
+        assert!(emulator.call_stack().frames().is_empty());
     }
 
-    pub fn run(&mut self) -> Result<(), Box<dyn Error>> {
-        loop {
-            let program_counter = self.cpu_state.pc.get();
-            let mut instruction = [0u8; 3];
+    #[test]
+    fn breakpoint_stops_before_the_instruction_and_resumes() {
+        let mut emulator = emulator(&[0x00, 0x00, 0x00]);
 
-            self.memory.read(program_counter.into(), &mut instruction);
+        emulator.add_breakpoint(0x04);
+        emulator.step().unwrap();
 
-            if self.interrupt_timers.interrupt {
-                if self.cpu_state.inte {
-                    let interrupt_number = self.interrupt_timers.number;
+        assert!(emulator.step().is_err());
+        assert_eq!(emulator.cpu_state.pc.get(), 0x04);
 
-                    instruction[0] = 0b11000111 | (interrupt_number << 3);
-                    self.cpu_state.inte = false;
-                }
-                self.interrupt_timers.interrupt = false;
-            }
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x05);
+    }
 
-            print!("{:04x}  ", program_counter);
+    #[test]
+    fn watched_accesses_are_queued() {
+        // MVI A,5; STA 0x2000; LDA 0x2001; OUT 3
+        let mut emulator = emulator(&[0x3e, 0x05, 0x32, 0x00, 0x20, 0x3a, 0x01, 0x20, 0xd3, 0x03]);
 
-            let decoded_instruction = disassembler::disassemble(&instruction)?;
+        emulator.watches_mut().watch_write(0x2000);
+        emulator.watches_mut().watch_read(0x2002);
+        emulator.watches_mut().watch_port_out(0x03);
 
-            match decoded_instruction {
-                Instruction::Stc => {
-                    self.cpu_state.psw.set_carry(1);
+        for _ in 0..4 {
+            emulator.step().unwrap();
+        }
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Cmc => {
-                    self.cpu_state.psw.set_carry(
-                        !self.cpu_state.psw.get_carry());
+        assert_eq!(emulator.take_watch_events(), [
+            WatchEvent::Write   { address: 0x2000, value: 0x05 },
+            WatchEvent::PortOut { port: 0x03, value: 0x00 },
+        ]);
+        assert!(emulator.take_watch_events().is_empty());
+    }
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Inr { reg } => {
-                    let register = self.get_register(reg).wrapping_add(1);
+    #[test]
+    fn profiler_follows_the_call_stack() {
+        // CALL 0x0010; at 0x0010 CALL 0x0020; at 0x0020 NOP; POP H; RET
+        let mut emulator = emulator(&[0xcd, 0x10, 0x00]);
 
-                    self.set_register(reg, register);
+        emulator.memory.write(0x10, &[0xcd, 0x20, 0x00]);
+        emulator.memory.write(0x20, &[0x00, 0xe1, 0xc9]);
+        emulator.enable_profiler();
 
-                    self.cpu_state.psw.set_parity(register);
-                    self.cpu_state.psw.set_zero(register);
-                    self.cpu_state.psw.set_sign(register);
+        for _ in 0..5 {
+            emulator.step().unwrap();
+        }
 
-                    self.cpu_state.pc += 1;
+        assert_eq!(emulator.cpu_state.pc.get(), 0x06);
+        assert_eq!(emulator.profiler().unwrap().folded(),
+                   "main 17\nmain;0010 27\nmain;0010;0020 14\n");
+    }
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 5;
-                    } else {
-                        self.interrupt_timers += 10;
-                    }
-                }
-                Instruction::Dcr { reg } => {
-                    let register = self.get_register(reg).wrapping_sub(1);
+    // LDA 0x2000; INR A; STA 0x2000 counts boots, then the program either
+    // spins or keeps writing to the watchdog port.
+    const COUNT_BOOTS: [u8; 7] = [0x3a, 0x00, 0x20, 0x3c, 0x32, 0x00, 0x20];
+    const SPIN:        [u8; 3] = [0xc3, 0x0a, 0x00];
+    const KICK:        [u8; 5] = [0xd3, 0x06, 0xc3, 0x0a, 0x00];
 
-                    self.set_register(reg, register);
+    fn boots(emulator: &mut Emulator, frames: usize) -> u8 {
+        for _ in 0..frames {
+            emulator.run_frame().unwrap();
+        }
+        emulator.memory().read8(0x2000)
+    }
 
-                    self.cpu_state.psw.set_parity(register);
-                    self.cpu_state.psw.set_zero(register);
-                    self.cpu_state.psw.set_sign(register);
+    #[test]
+    fn watchdog_resets_after_255_frames_without_a_kick() {
+        let mut emulator = emulator(&[&COUNT_BOOTS[..], &SPIN].concat());
 
-                    self.cpu_state.pc += 1;
+        assert_eq!(boots(&mut emulator, 254), 1);
+        assert_eq!(boots(&mut emulator, 2), 2);
+    }
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 5;
-                    } else {
-                        self.interrupt_timers += 10;
-                    }
-                }
-                Instruction::Cma => {
-                    self.cpu_state.psw.a = !self.cpu_state.psw.a;
+    #[test]
+    fn writing_port_6_kicks_the_watchdog() {
+        let mut emulator = emulator(&[&COUNT_BOOTS[..], &KICK].concat());
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Daa => {
-                    unimplemented!("DAA")
-                }
-                Instruction::Nop => {
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Mov { dst, src } => {
-                    let register = self.get_register(src);
+        assert_eq!(boots(&mut emulator, 600), 1);
+    }
 
-                    self.set_register(dst, register);
+    #[test]
+    fn disabled_watchdog_never_resets() {
+        let mut emulator = emulator(&[&COUNT_BOOTS[..], &SPIN].concat());
 
-                    self.cpu_state.pc += 1;
+        emulator.set_watchdog(false);
+        assert_eq!(boots(&mut emulator, 600), 1);
+    }
 
-                    if dst != 0b110 && src != 0b110 {
-                        self.interrupt_timers += 5;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Stax { rp } => {
-                    let register_pair = self.get_register_pair(rp);
+    fn run_until(emulator: &mut Emulator, cycles: u64) {
+        while emulator.cycles() < cycles {
+            emulator.step().unwrap();
+        }
+    }
 
-                    self.memory.write8(register_pair, self.cpu_state.psw.a);
+    #[test]
+    fn watchdog_times_out_in_cycles_after_the_last_kick() {
+        let mut emulator = Emulator::with_timing(Timing::new(2_000_000, 50).unwrap());
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Ldax { rp } => {
-                    let register_pair = self.get_register_pair(rp);
+        emulator.memory.write(0, &[&COUNT_BOOTS[..], &[0xd3, 0x06], &[0xc3, 0x09, 0x00]].concat());
 
-                    self.cpu_state.psw.a = self.memory.read8(register_pair);
+        // 255 frames at 50 Hz are 10200000 cycles after the kick at boot.
+        run_until(&mut emulator, 10_200_000);
+        assert_eq!(emulator.memory().read8(0x2000), 1);
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Add { reg } => {
-                    let (acc, cy) = self.cpu_state.psw.a
-                        .overflowing_add(self.get_register(reg));
+        run_until(&mut emulator, 10_200_100);
+        assert_eq!(emulator.memory().read8(0x2000), 2);
+    }
 
-                    self.cpu_state.psw.a = acc;
+    #[test]
+    fn devices_run_at_their_deadline_until_they_stop_rearming() {
+        fn count(emulator: &mut Emulator, due: u64) -> Option<u64> {
+            let count = emulator.memory.read8(0x2100) + 1;
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+            emulator.memory.write(0x2100, &[count]);
+            (count < 3).then_some(due + 1000)
+        }
 
-                    self.cpu_state.pc += 1;
+        let mut emulator = emulator(&[0xc3, 0x03, 0x00]);
+        let device       = emulator.add_device(count);
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Adc { reg } => {
-                    let register = self.get_register(reg);
+        emulator.schedule_device(device, 5000);
+        run_until(&mut emulator, 4990);
+        assert_eq!(emulator.memory().read8(0x2100), 0);
 
-                    let (acc, cy) = self.cpu_state.psw.a.carrying_add(
-                        register, self.cpu_state.psw.is_carry_set());
+        run_until(&mut emulator, 6010);
+        assert_eq!(emulator.memory().read8(0x2100), 2);
 
-                    self.cpu_state.psw.a = acc;
+        run_until(&mut emulator, 20_000);
+        assert_eq!(emulator.memory().read8(0x2100), 3);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        emulator.schedule_device(device, 25_000);
+        emulator.cancel_device(device);
+        run_until(&mut emulator, 30_000);
+        assert_eq!(emulator.memory().read8(0x2100), 3);
+    }
 
-                    self.cpu_state.pc += 1;
+    #[test]
+    fn reset_clears_pending_interrupts_and_the_shift_register() {
+        let mut emulator = emulator(&[0xfb, 0x00]);
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Sub { reg } => {
-                    let (acc, cy) = self.cpu_state.psw.a
-                        .overflowing_sub(self.get_register(reg));
+        emulator.io_ports.write(4, 0xab);
+        emulator.io_ports.write(4, 0xcd);
+        emulator.raise_interrupt(InterruptSource::Rst(7));
+        emulator.step().unwrap();
+        emulator.reset();
 
-                    self.cpu_state.psw.a = acc;
+        assert_eq!(emulator.io_ports.read(3), 0x00);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        emulator.cpu_state.inte = true;
+        emulator.step().unwrap();
 
-                    self.cpu_state.pc += 1;
+        assert_eq!(emulator.cpu_state.pc.get(), 0x03);
+    }
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Sbb { reg } => {
-                    let register = self.get_register(reg);
+    #[test]
+    fn patches_that_grow_the_rom_past_memory_are_rejected() {
+        let directory = std::env::temp_dir().join(format!("load-rom-{}", std::process::id()));
+        let rom       = std::fs::read("rom/space_invaders").unwrap();
+        let grown     = [&rom[..], &vec![0xff; 0x5001 - rom.len()]].concat();
+        let patch     = directory.join("grow.ips");
 
-                    let (acc, cy) = self.cpu_state.psw.a.borrowing_sub(
-                        register, self.cpu_state.psw.is_carry_set());
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(&patch, crate::patch::create_ips(&rom, &grown).unwrap()).unwrap();
 
-                    self.cpu_state.psw.a = acc;
+        let mut emulator = Emulator::new();
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert!(emulator.load_rom("rom/space_invaders", &[patch.to_str().unwrap()]).is_err());
+        assert!(emulator.memory().memory.iter().all(|&byte| byte == 0));
+    }
 
-                    self.cpu_state.pc += 1;
+    #[test]
+    fn saved_states_resume_on_another_emulator() {
+        let mut emulator = Emulator::new();
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Ana { reg } => {
-                    self.cpu_state.psw.a &= self.get_register(reg);
+        emulator.load_rom("rom/space_invaders", &[]).unwrap();
+        (0..90).for_each(|_| emulator.run_frame().unwrap());
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_zero(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_sign(self.cpu_state.psw.a);
+        let state        = emulator.save_state();
+        let mut restored = Emulator::new();
 
-                    self.cpu_state.pc += 1;
+        restored.load_state(&state).unwrap();
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Xra { reg } => {
-                    self.cpu_state.psw.a ^= self.get_register(reg);
+        for _ in 0..30 {
+            emulator.run_frame().unwrap();
+            restored.run_frame().unwrap();
+        }
+        assert_eq!(restored.registers(),     emulator.registers());
+        assert_eq!(restored.cycles(),        emulator.cycles());
+        assert_eq!(restored.memory().memory, emulator.memory().memory);
+        assert_eq!(restored.video().frame(), emulator.video().frame());
+        assert_eq!(restored.save_state(),    emulator.save_state());
+    }
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_zero(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_sign(self.cpu_state.psw.a);
+    #[test]
+    fn bad_states_leave_the_emulator_untouched() {
+        let mut emulator = emulator(&[0x3e, 0x42]);
+        let state        = emulator.save_state();
 
-                    self.cpu_state.pc += 1;
+        emulator.step().unwrap();
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Ora { reg } => {
-                    self.cpu_state.psw.a |= self.get_register(reg);
+        assert!(emulator.load_state(&state[..state.len() - 1]).is_err());
+        assert!(emulator.load_state(b"not a state").is_err());
+        assert_eq!(emulator.registers().a, 0x42);
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_zero(self.cpu_state.psw.a);
-                    self.cpu_state.psw.set_sign(self.cpu_state.psw.a);
+        emulator.load_state(&state).unwrap();
+        assert_eq!(emulator.registers().a, 0x00);
+    }
 
-                    self.cpu_state.pc += 1;
+    #[test]
+    fn strict_mode_stops_before_acknowledging_an_interrupt() {
+        let mut emulator = emulator(&[0xfb, 0x00, 0xcb, 0x00, 0x00]);
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Cmp { reg } => {
-                    let (acc, cy) = self.cpu_state.psw.a
-                        .overflowing_sub(self.get_register(reg));
+        emulator.set_strict(true);
+        emulator.step().unwrap();
+        emulator.step().unwrap();
+        emulator.raise_interrupt(InterruptSource::Rst(1));
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert!(emulator.step().is_err());
+        assert_eq!(emulator.cpu_state.pc.get(), 0x05);
+        assert!(emulator.cpu_state.inte);
 
-                    self.cpu_state.pc += 1;
+        emulator.set_strict(false);
+        emulator.step().unwrap();
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 4;
-                    } else {
-                        self.interrupt_timers += 7;
-                    }
-                }
-                Instruction::Rlc => {
-                    let acc = self.cpu_state.psw.a;
+        assert_eq!(emulator.cpu_state.pc.get(), 0x08);
+    }
 
-                    let (acc, cy) = (acc.rotate_left(1), acc >> 7);
+    #[test]
+    fn undocumented_8080_opcodes_alias_jumps_calls_and_returns() {
+        let mut emulator = emulator(&[0xcb, 0x10, 0x00]);
 
-                    self.cpu_state.psw.a = acc;
-                    self.cpu_state.psw.set_carry(cy);
+        emulator.memory.write(0x10, &[0xdd, 0x20, 0x00, 0xed, 0x20, 0x00, 0xfd, 0x20, 0x00]);
+        emulator.memory.write(0x20, &[0xd9]);
+        emulator.step().unwrap();
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Rrc => {
-                    let acc = self.cpu_state.psw.a;
+        assert_eq!(emulator.cpu_state.pc.get(), 0x10);
 
-                    let (acc, cy) = (acc.rotate_right(1), acc & 1);
+        for return_address in [0x13, 0x16, 0x19] {
+            emulator.step().unwrap();
 
-                    self.cpu_state.psw.a = acc;
-                    self.cpu_state.psw.set_carry(cy);
+            assert_eq!(emulator.cpu_state.pc.get(), 0x20);
+            assert_eq!(emulator.cpu_state.sp.get(), 0x23fe);
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Ral => {
-                    let mut acc = self.cpu_state.psw.a;
-                    let mut cy  = self.cpu_state.psw.get_carry();
+            emulator.step().unwrap();
 
-                    (acc, cy) = ((acc << 1) | cy , acc & 0x80);
+            assert_eq!(emulator.cpu_state.pc.get(), return_address);
+            assert_eq!(emulator.cpu_state.sp.get(), 0x2400);
+        }
+    }
 
-                    self.cpu_state.psw.a = acc;
-                    self.cpu_state.psw.set_carry(cy);
+    #[test]
+    fn strict_mode_rejects_every_8080_alias() {
+        for opcode in [0xcb, 0xd9, 0xdd, 0xed, 0xfd] {
+            let mut emulator = emulator(&[opcode, 0x10, 0x00]);
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Rar => {
-                    let mut acc = self.cpu_state.psw.a;
-                    let mut cy  = self.cpu_state.psw.get_carry();
+            emulator.set_strict(true);
 
-                    (acc, cy) = ((acc >> 1) | (cy << 7), acc & 1);
+            assert!(emulator.step().is_err());
+            assert_eq!(emulator.cpu_state.pc.get(), 0x03);
+        }
+    }
 
-                    self.cpu_state.psw.a = acc;
-                    self.cpu_state.psw.set_carry(cy);
+    // Runs a program from address 0 until the program counter leaves it.
+    fn execute(program: &[u8]) -> Emulator {
+        let mut emulator = Emulator::new();
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Push { rp } => {
-                    let register_pair = self.get_register_pair(rp);
+        emulator.memory.write(0, program);
 
-                    self.cpu_state.sp -= 2;
+        while usize::from(emulator.cpu_state.pc.get()) < program.len() {
+            emulator.step().unwrap();
+        }
+        emulator
+    }
 
-                    self.write_sp(register_pair);
+    #[test]
+    fn daa_adjusts_the_low_digit() {
+        // MVI A,0x38; ADI 0x45; DAA
+        let emulator = execute(&[0x3e, 0x38, 0xc6, 0x45, 0x27]);
+        let psw      = &emulator.cpu_state.psw;
 
-                    self.cpu_state.pc     +=  1;
-                    self.interrupt_timers += 11;
-                }
-                Instruction::Pop { rp } => {
-                    let register_pair = self.read_sp();
+        assert_eq!(psw.a, 0x83);
+        assert!(psw.is_aux_carry_set());
+        assert!(!psw.is_carry_set());
+    }
 
-                    self.cpu_state.sp += 2;
+    #[test]
+    fn daa_uses_the_aux_carry_of_the_addition() {
+        // MVI A,0x09; ADI 0x09; DAA
+        let emulator = execute(&[0x3e, 0x09, 0xc6, 0x09, 0x27]);
 
-                    self.set_register_pair(rp, register_pair);
+        assert_eq!(emulator.cpu_state.psw.a, 0x18);
+        assert!(!emulator.cpu_state.psw.is_carry_set());
+    }
 
-                    self.cpu_state.pc     +=  1;
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Dad { rp } => {
-                    let register_pair = self.get_register_pair(rp);
+    #[test]
+    fn daa_carries_out_of_the_high_digit() {
+        // MVI A,0x99; ADI 0x01; DAA
+        let emulator = execute(&[0x3e, 0x99, 0xc6, 0x01, 0x27]);
+        let psw      = &emulator.cpu_state.psw;
 
-                    let (hl, cy) = self.cpu_state.hl.get()
-                        .overflowing_add(register_pair);
+        assert_eq!(psw.a, 0x00);
+        assert!(psw.is_carry_set());
+        assert!(psw.is_zero_set());
+    }
 
-                    self.cpu_state.hl.set(hl);
-                    self.cpu_state.psw.set_carry(u8::from(cy));
+    #[test]
+    fn inr_and_dcr_set_aux_carry_on_the_low_digit() {
+        // MVI B,0x0f; INR B
+        let emulator = execute(&[0x06, 0x0f, 0x04]);
+        assert!(emulator.cpu_state.psw.is_aux_carry_set());
 
-                    self.cpu_state.pc     +=  1;
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Inx { rp } => {
-                    let register_pair = self.get_register_pair(rp)
-                        .wrapping_add(1);
+        // MVI B,0x10; DCR B
+        let emulator = execute(&[0x06, 0x10, 0x05]);
+        assert!(!emulator.cpu_state.psw.is_aux_carry_set());
 
-                    self.set_register_pair(rp, register_pair);
+        // MVI B,0x11; DCR B
+        let emulator = execute(&[0x06, 0x11, 0x05]);
+        assert!(emulator.cpu_state.psw.is_aux_carry_set());
+    }
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 5;
-                }
-                Instruction::Dcx { rp } => {
-                    let register_pair = self.get_register_pair(rp)
-                        .wrapping_sub(1);
+    #[test]
+    fn subtraction_sets_aux_carry_without_a_borrow_from_the_low_digit() {
+        // MVI A,0x10; SUI 0x01
+        let emulator = execute(&[0x3e, 0x10, 0xd6, 0x01]);
+        assert!(!emulator.cpu_state.psw.is_aux_carry_set());
 
-                    self.set_register_pair(rp, register_pair);
+        // MVI A,0x11; SUI 0x01
+        let emulator = execute(&[0x3e, 0x11, 0xd6, 0x01]);
+        assert!(emulator.cpu_state.psw.is_aux_carry_set());
+    }
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 5;
-                }
-                Instruction::Xchg => {
-                    std::mem::swap(&mut self.cpu_state.de,
-                        &mut self.cpu_state.hl);
+    #[test]
+    fn logical_operations_set_aux_carry_like_the_8080() {
+        // MVI A,0x08; ANI 0x00
+        let emulator = execute(&[0x3e, 0x08, 0xe6, 0x00]);
+        assert!(emulator.cpu_state.psw.is_aux_carry_set());
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Xthl => {
-                    let tmp = self.read_sp();
+        // MVI A,0x0f; ADI 0x01; XRI 0x0f
+        let emulator = execute(&[0x3e, 0x0f, 0xc6, 0x01, 0xee, 0x0f]);
+        assert!(!emulator.cpu_state.psw.is_aux_carry_set());
+    }
+}
 
-                    self.write_sp(self.cpu_state.hl.get());
-                    self.cpu_state.hl.set(tmp);
+#[cfg(test)]
+mod tests_8085 {
+    use super::*;
 
-                    self.cpu_state.pc     +=  1;
-                    self.interrupt_timers += 18;
-                }
-                Instruction::Sphl => {
-                    self.cpu_state.sp.set(self.cpu_state.hl.get());
+    const LXI_SP: [u8; 3] = [0x31, 0x00, 0x24];
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 5;
-                }
-                Instruction::Lxi { rp, data } => {
-                    self.set_register_pair(rp, data);
+    fn emulator(cpu: Cpu, program: &[u8]) -> Emulator {
+        let mut emulator = Emulator::with_cpu(cpu, Timing::default());
 
-                    self.cpu_state.pc     +=  3;
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Mvi { reg, data } => {
-                    self.set_register(reg, data);
+        emulator.memory_mut().write(0, &LXI_SP);
+        emulator.memory_mut().write(LXI_SP.len(), program);
+        emulator.step().unwrap();
+        emulator
+    }
 
-                    self.cpu_state.pc += 2;
+    fn run(emulator: &mut Emulator, steps: usize) -> u64 {
+        (0..steps).map(|_| u64::from(emulator.step().unwrap())).sum()
+    }
 
-                    if reg != 0b110 {
-                        self.interrupt_timers += 7;
-                    } else {
-                        self.interrupt_timers += 10;
-                    }
-                }
-                Instruction::Adi { data } => {
-                    let (acc, cy) = self.cpu_state.psw.a.overflowing_add(data);
+    #[test]
+    fn sim_sets_masks_and_rim_reports_pending_lines() {
+        let mut emulator = emulator(Cpu::I8085, &[0x3e, 0x0d, 0x30, 0x20]);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.set_interrupt_line(InterruptLine::Rst65, true);
+        emulator.set_sid(true);
+        run(&mut emulator, 3);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert_eq!(emulator.cpu_state.psw.a, 0x80 | 0x20 | 0x05);
+    }
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Aci { data } => {
-                    let (acc, cy) = self.cpu_state.psw.a.carrying_add(
-                        data, self.cpu_state.psw.is_carry_set());
+    #[test]
+    fn sim_drives_the_serial_output() {
+        let mut emulator = emulator(Cpu::I8085, &[0x3e, 0xc0, 0x30, 0x3e, 0x40, 0x30]);
 
-                    self.cpu_state.psw.a = acc;
+        run(&mut emulator, 2);
+        assert!(emulator.sod());
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        run(&mut emulator, 2);
+        assert!(!emulator.sod());
+    }
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Sui { data } => {
-                    let (acc, cy) = self.cpu_state.psw.a.overflowing_sub(data);
+    #[test]
+    fn unmasked_rst65_vectors_after_the_ei_delay() {
+        let mut emulator = emulator(Cpu::I8085, &[0x3e, 0x08, 0x30, 0xfb, 0x00, 0x00]);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.set_interrupt_line(InterruptLine::Rst65, true);
+        run(&mut emulator, 4);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x08);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert_eq!(emulator.step().unwrap(), VECTOR_CYCLES);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x34);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x08);
+        assert!(!emulator.cpu_state.inte);
+    }
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Sbi { data } => {
-                    let (acc, cy) = self.cpu_state.psw.a.borrowing_sub(
-                        data, self.cpu_state.psw.is_carry_set());
+    #[test]
+    fn masked_lines_are_ignored() {
+        let mut emulator = emulator(Cpu::I8085, &[0xfb, 0x00, 0x00, 0x00]);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.set_interrupt_line(InterruptLine::Rst55, true);
+        emulator.set_interrupt_line(InterruptLine::Rst65, true);
+        run(&mut emulator, 4);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x07);
+    }
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Ani { data } => {
-                    let acc = self.cpu_state.psw.a & data;
+    #[test]
+    fn rst75_latches_a_rising_edge() {
+        let mut emulator = emulator(Cpu::I8085, &[0x3e, 0x08, 0x30, 0xfb, 0x00]);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.set_interrupt_line(InterruptLine::Rst75, true);
+        emulator.set_interrupt_line(InterruptLine::Rst75, false);
+        run(&mut emulator, 5);
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x3c);
+    }
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Xri { data } => {
-                    let acc = self.cpu_state.psw.a ^ data;
+    #[test]
+    fn trap_ignores_inte_and_rim_reports_the_previous_state() {
+        let mut emulator = emulator(Cpu::I8085, &[0xfb, 0x00]);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.memory_mut().write(0x24, &[0x20, 0x20]);
+        emulator.step().unwrap();
+        emulator.set_interrupt_line(InterruptLine::Trap, true);
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.pc.get(), 0x24);
+        assert!(!emulator.cpu_state.inte);
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Ori { data } => {
-                    let acc = self.cpu_state.psw.a | data;
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.psw.a & 0x08, 0x08);
 
-                    self.cpu_state.psw.a = acc;
+        emulator.step().unwrap();
+        assert_eq!(emulator.cpu_state.psw.a & 0x08, 0x00);
+    }
 
-                    self.cpu_state.psw.set_carry(0);
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+    #[test]
+    fn trap_wakes_a_halt_with_interrupts_disabled() {
+        let mut emulator = emulator(Cpu::I8085, &[0xf3, 0x76]);
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Cpi { data } => {
-                    let (acc, cy) = self.cpu_state.psw.a.overflowing_sub(data);
+        run(&mut emulator, 3);
+        assert!(emulator.cpu_state.halted);
 
-                    self.cpu_state.psw.set_carry(u8::from(cy));
-                    self.cpu_state.psw.set_parity(acc);
-                    self.cpu_state.psw.set_zero(acc);
-                    self.cpu_state.psw.set_sign(acc);
+        emulator.set_interrupt_line(InterruptLine::Trap, true);
+        emulator.step().unwrap();
 
-                    self.cpu_state.pc     += 2;
-                    self.interrupt_timers += 7;
-                }
-                Instruction::Sta { exp } => {
-                    self.memory.write8(exp, self.cpu_state.psw.a);
+        assert!(!emulator.cpu_state.halted);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x24);
+    }
 
-                    self.cpu_state.pc     +=  3;
-                    self.interrupt_timers += 13;
-                }
-                Instruction::Lda { exp } => {
-                    self.cpu_state.psw.a = self.memory.read8(exp);
+    #[test]
+    fn timings_differ_from_the_8080() {
+        // MOV B,C; PUSH B; CALL 0x000b; HLT; then ORA A; JZ (not taken);
+        // INX B; CZ (not taken); RNZ (taken) back to the HLT.
+        let program = [0x41, 0xc5, 0xcd, 0x0b, 0x00, 0x76, 0x00, 0x00,
+                       0xb7, 0xca, 0x00, 0x00, 0x03, 0xcc, 0x00, 0x00, 0xc0];
 
-                    self.cpu_state.pc     +=  3;
-                    self.interrupt_timers += 13;
-                }
-                Instruction::Shld { exp } => {
-                    self.memory.write16(exp, self.cpu_state.hl.get());
+        let expected = [
+            (Cpu::I8080, [5, 11, 17, 4, 10, 5, 11, 11, 7]),
+            (Cpu::I8085, [4, 12, 18, 4,  7, 6,  9, 12, 5]),
+        ];
 
-                    self.cpu_state.pc     +=  3;
-                    self.interrupt_timers += 16;
-                }
-                Instruction::Lhld { exp } => {
-                    self.cpu_state.hl.set(self.memory.read16(exp));
+        for (cpu, cycles) in expected {
+            let mut emulator = emulator(cpu, &program);
 
-                    self.cpu_state.pc     +=  3;
-                    self.interrupt_timers += 16;
-                }
-                Instruction::Pchl => {
-                    self.cpu_state.pc.set(self.cpu_state.hl.get());
-                    self.interrupt_timers += 5;
-                }
-                Instruction::Jmp { exp } => {
-                    self.cpu_state.pc.set(exp);
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jc { exp } => {
-                    if self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jnc { exp } => {
-                    if !self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jz { exp } => {
-                    if self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jnz { exp } => {
-                    if !self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jm { exp } => {
-                    if self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jp { exp } => {
-                    if !self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jpe { exp } => {
-                    if self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Jpo { exp } => {
-                    if !self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.pc.set(exp);
-                    } else {
-                        self.cpu_state.pc += 3;
-                    }
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Call { sub } => {
-                    self.cpu_state.pc += 3;
-                    self.cpu_state.sp -= 2;
+            emulator.cpu_state.psw.a = 1;
 
-                    self.write_sp(self.cpu_state.pc.get());
-                    self.cpu_state.pc.set(sub);
+            let taken = cycles.map(|_| emulator.step().unwrap());
+            assert_eq!(taken, cycles, "{:?}", cpu);
+        }
+    }
 
-                    self.interrupt_timers += 17;
-                }
-                Instruction::Cc { sub } => {
-                    self.cpu_state.pc += 3;
+    #[test]
+    fn dsub_sets_overflow_and_underflow() {
+        let mut emulator = emulator(Cpu::I8085, &[0x08]);
 
-                    if self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.sp -= 2;
+        emulator.cpu_state.hl.set(0x8000);
+        emulator.cpu_state.bc.set(0x0001);
+        emulator.step().unwrap();
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        let psw = &emulator.cpu_state.psw;
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cnc { sub } => {
-                    self.cpu_state.pc += 3;
+        assert_eq!(emulator.cpu_state.hl.get(), 0x7fff);
+        assert!(psw.is_overflow_set());
+        assert!(psw.is_underflow_set());
+        assert!(!psw.is_sign_set());
+        assert!(!psw.is_carry_set());
+        assert!(!psw.is_zero_set());
+    }
 
-                    if !self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.sp -= 2;
+    #[test]
+    fn dsub_sets_zero_from_the_whole_result() {
+        let mut emulator = emulator(Cpu::I8085, &[0x08, 0x08]);
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        emulator.cpu_state.hl.set(0x1234);
+        emulator.cpu_state.bc.set(0x0134);
+        emulator.step().unwrap();
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cz { sub } => {
-                    self.cpu_state.pc += 3;
+        assert_eq!(emulator.cpu_state.hl.get(), 0x1100);
+        assert!(!emulator.cpu_state.psw.is_zero_set());
 
-                    if self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.sp -= 2;
+        emulator.cpu_state.bc.set(0x1100);
+        emulator.step().unwrap();
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        assert_eq!(emulator.cpu_state.hl.get(), 0x0000);
+        assert!(emulator.cpu_state.psw.is_zero_set());
+    }
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cnz { sub } => {
-                    self.cpu_state.pc += 3;
+    #[test]
+    fn dsub_borrows_without_overflow() {
+        let mut emulator = emulator(Cpu::I8085, &[0x08]);
 
-                    if !self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.sp -= 2;
+        emulator.cpu_state.hl.set(0x0001);
+        emulator.cpu_state.bc.set(0x0002);
+        emulator.step().unwrap();
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        let psw = &emulator.cpu_state.psw;
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cm { sub } => {
-                    self.cpu_state.pc += 3;
+        assert_eq!(emulator.cpu_state.hl.get(), 0xffff);
+        assert!(!psw.is_overflow_set());
+        assert!(psw.is_underflow_set());
+        assert!(psw.is_sign_set());
+        assert!(psw.is_carry_set());
+    }
 
-                    if self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.sp -= 2;
+    #[test]
+    fn subtraction_sets_overflow_on_the_8085_only() {
+        // MVI A,0x80; SUI 0x01
+        for (cpu, overflow) in [(Cpu::I8080, false), (Cpu::I8085, true)] {
+            let mut emulator = emulator(cpu, &[0x3e, 0x80, 0xd6, 0x01]);
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+            emulator.cpu_state.psw.set_overflow(0);
+            run(&mut emulator, 2);
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cp { sub } => {
-                    self.cpu_state.pc += 3;
+            assert_eq!(emulator.cpu_state.psw.a, 0x7f);
+            assert_eq!(emulator.cpu_state.psw.is_overflow_set(), overflow, "{:?}", cpu);
+        }
+    }
 
-                    if !self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.sp -= 2;
+    #[test]
+    fn rdel_rotates_de_through_carry() {
+        let mut emulator = emulator(Cpu::I8085, &[0x37, 0x18, 0x18]);
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        emulator.cpu_state.de.set(0x4000);
+        run(&mut emulator, 2);
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cpe { sub } => {
-                    self.cpu_state.pc += 3;
+        assert_eq!(emulator.cpu_state.de.get(), 0x8001);
+        assert!(!emulator.cpu_state.psw.is_carry_set());
+        assert!(emulator.cpu_state.psw.is_overflow_set());
 
-                    if self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.sp -= 2;
+        emulator.step().unwrap();
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        assert_eq!(emulator.cpu_state.de.get(), 0x0002);
+        assert!(emulator.cpu_state.psw.is_carry_set());
+        assert!(emulator.cpu_state.psw.is_overflow_set());
+    }
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Cpo { sub } => {
-                    self.cpu_state.pc += 3;
+    #[test]
+    fn arhl_shifts_hl_arithmetically() {
+        let mut emulator = emulator(Cpu::I8085, &[0x10]);
 
-                    if !self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.sp -= 2;
+        emulator.cpu_state.hl.set(0x8003);
+        emulator.step().unwrap();
 
-                        self.write_sp(self.cpu_state.pc.get());
-                        self.cpu_state.pc.set(sub);
+        assert_eq!(emulator.cpu_state.hl.get(), 0xc001);
+        assert!(emulator.cpu_state.psw.is_carry_set());
+    }
 
-                        self.interrupt_timers += 17;
-                    } else {
-                        self.interrupt_timers += 11;
-                    }
-                }
-                Instruction::Ret => {
-                    self.cpu_state.pc.set(self.read_sp());
+    #[test]
+    fn handlers_only_change_the_flags_the_decoder_reports() {
+        let opcodes = [0x03, 0x04, 0x05, 0x07, 0x08, 0x09, 0x0b, 0x10, 0x18, 0x1f, 0x27, 0x37,
+                       0x3f, 0x80, 0x8e, 0x97, 0xa0, 0xaf, 0xb0, 0xb8, 0xc6, 0xde, 0xe6, 0xf1, 0xfe];
 
-                    self.cpu_state.sp     +=  2;
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Rc => {
-                    if self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rnc => {
-                    if !self.cpu_state.psw.is_carry_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rz => {
-                    if self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rnz => {
-                    if !self.cpu_state.psw.is_zero_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rm  => {
-                    if self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rp => {
-                    if !self.cpu_state.psw.is_sign_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rpe => {
-                    if self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rpo => {
-                    if !self.cpu_state.psw.is_parity_set() {
-                        self.cpu_state.pc.set(self.read_sp());
-
-                        self.cpu_state.sp     +=  2;
-                        self.interrupt_timers += 11;
-                    } else {
-                        self.cpu_state.pc     += 1;
-                        self.interrupt_timers += 5;
-                    }
-                }
-                Instruction::Rst { exp } => {
-                    self.cpu_state.sp -= 2;
+        for cpu in [Cpu::I8080, Cpu::I8085] {
+            for opcode in opcodes {
+                let written = decoder::decode(cpu, &[opcode, 0x00, 0x00]).unwrap().flags_written(cpu);
 
-                    self.write_sp(self.cpu_state.pc.get());
+                for flags in [0x00, 0xf7] {
+                    let mut emulator = emulator(cpu, &[opcode, 0x00, 0x00]);
 
-                    self.cpu_state.pc.set(u16::from(exp * 8));
-                    self.interrupt_timers += 11;
-                }
-                Instruction::Ei => {
-                    self.cpu_state.inte = true;
+                    emulator.cpu_state.psw.set(emulator.psw_bits(0x5500 | flags));
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::Di => {
-                    self.cpu_state.inte = false;
+                    let before = emulator.cpu_state.psw.flags();
 
-                    self.cpu_state.pc     += 1;
-                    self.interrupt_timers += 4;
-                }
-                Instruction::In { exp } => {
-                    self.cpu_state.psw.a = self.io_ports.read(exp);
+                    emulator.step().unwrap();
 
-                    self.cpu_state.pc     +=  2;
-                    self.interrupt_timers += 10;
-                }
-                Instruction::Out { exp } => {
-                    self.io_ports.write(exp, self.cpu_state.psw.a);
+                    let changed = before ^ emulator.cpu_state.psw.flags();
 
-                    self.cpu_state.pc     +=  2;
-                    self.interrupt_timers += 10;
+                    assert_eq!(changed & !written, 0, "{:?} {:02x}", cpu, opcode);
                 }
-                Instruction::Hlt => {
-                    return Ok(())
-                }
-            };
+            }
         }
     }
+
+    #[test]
+    fn inx_and_dcx_set_k_when_they_wrap() {
+        // LXI B,0xffff; INX B; JK 0x0100
+        let mut emulator = emulator(Cpu::I8085, &[0x01, 0xff, 0xff, 0x03, 0xfd, 0x00, 0x01]);
+
+        run(&mut emulator, 2);
+        assert!(emulator.cpu_state.psw.is_underflow_set());
+
+        assert_eq!(emulator.step().unwrap(), 10);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x0100);
+
+        // DCX B; JNK 0x0200; DCX B; JNK 0x0200
+        emulator.memory_mut().write(0x0100, &[0x0b, 0xdd, 0x00, 0x02, 0x0b, 0xdd, 0x00, 0x02]);
+
+        run(&mut emulator, 1);
+        assert!(emulator.cpu_state.psw.is_underflow_set());
+        assert_eq!(emulator.step().unwrap(), 7);
+
+        run(&mut emulator, 2);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x0200);
+    }
+
+    #[test]
+    fn rstv_calls_0x40_on_overflow() {
+        // MVI A,0x7f; ADI 0x01; RSTV
+        let mut emulator = emulator(Cpu::I8085, &[0x3e, 0x7f, 0xc6, 0x01, 0xcb]);
+
+        run(&mut emulator, 2);
+
+        assert_eq!(emulator.step().unwrap(), 12);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x40);
+        assert_eq!(emulator.memory().read16(0x23fe), 0x08);
+    }
+
+    #[test]
+    fn ldhi_ldsi_shlx_and_lhlx_address_through_de() {
+        // LXI H,0x2000; LDHI 0x10; SHLX; LDSI 0x02; LHLX
+        let mut emulator = emulator(Cpu::I8085, &[0x21, 0x00, 0x20, 0x28, 0x10, 0xd9,
+                                                  0x38, 0x02, 0xed]);
+
+        run(&mut emulator, 3);
+
+        assert_eq!(emulator.cpu_state.de.get(), 0x2010);
+        assert_eq!(emulator.memory().read16(0x2010), 0x2000);
+
+        emulator.memory_mut().write16(0x2402, 0xbeef);
+        run(&mut emulator, 2);
+
+        assert_eq!(emulator.cpu_state.de.get(), 0x2402);
+        assert_eq!(emulator.cpu_state.hl.get(), 0xbeef);
+    }
+
+    #[test]
+    fn undocumented_8085_opcodes_are_aliases_on_the_8080() {
+        let mut emulator = emulator(Cpu::I8080, &[0x08, 0x20, 0x30]);
+
+        emulator.cpu_state.hl.set(0x1234);
+        run(&mut emulator, 3);
+
+        assert_eq!(emulator.cpu_state.hl.get(), 0x1234);
+        assert_eq!(emulator.cpu_state.pc.get(), 0x06);
+    }
 }
@@ -0,0 +1,308 @@
+use std::error::Error;
+
+use crate::emulator::Emulator;
+use crate::io_ports::Button;
+use crate::video::{HEIGHT, WIDTH};
+
+const SCORE:        u16 = 0x20f8;
+const GAME_MODE:    u16 = 0x20ef;
+const GAME_RUNNING: u16 = 0x20e9;
+
+const BOOT_FRAMES:   u64 = 100;
+const BUTTON_FRAMES: u64 = 100;
+const START_TIMEOUT: u64 = 1000;
+
+// The minimal action set, in the order other emulators number it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Noop,
+    Fire,
+    Right,
+    Left,
+    RightFire,
+    LeftFire,
+}
+
+impl Action {
+    pub const ALL: [Action; 6] = [
+        Action::Noop, Action::Fire, Action::Right, Action::Left, Action::RightFire, Action::LeftFire,
+    ];
+
+    fn buttons(self) -> (bool, bool, bool) {
+        match self {
+            Action::Noop      => (false, false, false),
+            Action::Fire      => (true,  false, false),
+            Action::Right     => (false, false, true),
+            Action::Left      => (false, true,  false),
+            Action::RightFire => (true,  false, true),
+            Action::LeftFire  => (true,  true,  false),
+        }
+    }
+}
+
+impl TryFrom<usize> for Action {
+    type Error = String;
+
+    fn try_from(index: usize) -> Result<Action, String> {
+        Action::ALL.get(index).copied().ok_or(format!("no action {}", index))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EnvConfig {
+    pub frame_skip: u32,
+    pub downsample: usize,
+    pub sticky:     f64,
+    pub noop_max:   u32,
+    pub seed:       u64,
+}
+
+impl Default for EnvConfig {
+    fn default() -> Self {
+        EnvConfig {
+            frame_skip: 4,
+            downsample: 2,
+            sticky:     0.0,
+            noop_max:   0,
+            seed:       0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub observation: Vec<u8>,
+    pub reward:      i32,
+    pub done:        bool,
+}
+
+// SplitMix64. Sticky actions and no-op starts draw from it so an episode
+// depends only on the seed and the actions.
+#[derive(Clone)]
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng {
+            state: seed,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        probability > 0.0 && ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+
+    fn below(&mut self, limit: u32) -> u32 {
+        (self.next() % u64::from(limit)) as u32
+    }
+}
+
+fn run_frames(emulator: &mut Emulator, count: u64) -> Result<(), Box<dyn Error>> {
+    for _ in 0..count {
+        emulator.run_frame()?;
+    }
+    Ok(())
+}
+
+fn decimal(bcd: u16) -> i32 {
+    (0..4).rev().fold(0, |value, digit| value * 10 + i32::from((bcd >> (digit * 4)) & 0xf))
+}
+
+// A one player game seen through the screen, the score and the game mode.
+// Fire is edge triggered by the game, so repeating a fire action without
+// a release in between only shoots once.
+#[derive(Clone)]
+pub struct Env {
+    rom:      Vec<u8>,
+    config:   EnvConfig,
+    start:    Option<Emulator>,
+    emulator: Emulator,
+    rng:      Rng,
+    action:   Action,
+    score:    i32,
+    done:     bool,
+}
+
+impl Env {
+    pub fn new(rom: Vec<u8>, config: EnvConfig) -> Result<Env, Box<dyn Error>> {
+        if !WIDTH.is_multiple_of(config.downsample) || !HEIGHT.is_multiple_of(config.downsample) {
+            return Err(format!("downsample {} does not divide {}x{}", config.downsample, WIDTH, HEIGHT).into());
+        }
+        if config.frame_skip == 0 {
+            return Err("frame skip must be at least 1".into());
+        }
+        if !(0.0..=1.0).contains(&config.sticky) {
+            return Err(format!("sticky probability {} is not between 0 and 1", config.sticky).into());
+        }
+
+        let emulator = Emulator::new();
+
+        if rom.len() > emulator.memory().len() {
+            return Err(format!("ROM of {} bytes does not fit in {} bytes of memory",
+                               rom.len(), emulator.memory().len()).into());
+        }
+
+        Ok(Env {
+            rom,
+            config,
+            start:    None,
+            emulator,
+            rng:      Rng::new(config.seed),
+            action:   Action::Noop,
+            score:    0,
+            done:     true,
+        })
+    }
+
+    pub fn config(&self) -> &EnvConfig {
+        &self.config
+    }
+
+    pub fn emulator(&self) -> &Emulator {
+        &self.emulator
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    // Width and height of an observation, one byte per pixel.
+    pub fn observation_shape(&self) -> (usize, usize) {
+        (WIDTH / self.config.downsample, HEIGHT / self.config.downsample)
+    }
+
+    pub fn score(&self) -> i32 {
+        self.score
+    }
+
+    // The first reset boots the machine, inserts a coin and starts a game,
+    // and keeps a copy of the machine at that point. Every reset starts
+    // from that copy and then runs a random number of no-op steps up to
+    // noop_max.
+    pub fn reset(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let start = match self.start.take() {
+            Some(start) => start,
+            None        => self.boot()?,
+        };
+
+        self.emulator = start.clone();
+        self.start    = Some(start);
+
+        self.action = Action::Noop;
+        self.score  = decimal(self.emulator.memory().read16(SCORE));
+        self.done   = false;
+
+        let noops = if self.config.noop_max > 0 { self.rng.below(self.config.noop_max + 1) } else { 0 };
+
+        for _ in 0..noops {
+            self.act(Action::Noop)?;
+        }
+        Ok(self.observation())
+    }
+
+    pub fn step(&mut self, action: Action) -> Result<Step, Box<dyn Error>> {
+        let (reward, done) = self.act(action)?;
+
+        Ok(Step {
+            observation: self.observation(),
+            reward,
+            done,
+        })
+    }
+
+    // Runs the frames of a step and returns the reward and whether the
+    // game is over, without building an observation.
+    pub fn act(&mut self, action: Action) -> Result<(i32, bool), Box<dyn Error>> {
+        if self.done {
+            return Err("the episode is over, reset the environment".into());
+        }
+
+        let start = self.score;
+
+        for _ in 0..self.config.frame_skip {
+            if !self.rng.chance(self.config.sticky) {
+                self.action = action;
+            }
+
+            let (fire, left, right) = self.action.buttons();
+
+            self.emulator.set_button(Button::P1Fire, fire);
+            self.emulator.set_button(Button::P1Left, left);
+            self.emulator.set_button(Button::P1Right, right);
+            self.emulator.run_frame()?;
+
+            // The score wraps from 9990 back to 0.
+            let score = decimal(self.emulator.memory().read16(SCORE));
+
+            self.score += (score - self.score).rem_euclid(10000);
+            self.done   = self.emulator.memory().read8(GAME_MODE) == 0;
+
+            if self.done {
+                break;
+            }
+        }
+
+        Ok((self.score - start, self.done))
+    }
+
+    // Each output pixel is 255 if any pixel of its block is lit, so single
+    // pixel wide shots survive the downsampling.
+    pub fn observation(&self) -> Vec<u8> {
+        let (width, height) = self.observation_shape();
+        let mut observation = vec![0u8; width * height];
+
+        self.observation_into(&mut observation);
+        observation
+    }
+
+    // Writes the observation into a slice of exactly the observation size.
+    pub fn observation_into(&self, observation: &mut [u8]) {
+        let scale = self.config.downsample;
+        let width = WIDTH / scale;
+
+        observation.fill(0);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                if self.emulator.video().pixel(x, y) {
+                    observation[y / scale * width + x / scale] = 0xff;
+                }
+            }
+        }
+    }
+
+    fn boot(&self) -> Result<Emulator, Box<dyn Error>> {
+        let mut emulator = Emulator::new();
+
+        emulator.memory_mut().write(0, &self.rom);
+        run_frames(&mut emulator, BOOT_FRAMES)?;
+
+        for button in [Button::Coin, Button::P1Start] {
+            emulator.set_button(button, true);
+            run_frames(&mut emulator, 1)?;
+            emulator.set_button(button, false);
+            run_frames(&mut emulator, BUTTON_FRAMES)?;
+        }
+
+        let mut waited = 0;
+
+        while emulator.memory().read8(GAME_RUNNING) == 0 {
+            if waited == START_TIMEOUT {
+                return Err("the game did not start".into());
+            }
+            run_frames(&mut emulator, 1)?;
+            waited += 1;
+        }
+        Ok(emulator)
+    }
+}
@@ -1,8 +1,11 @@
 pub enum Flag {
-    Carry  = 1 << 0,
-    Parity = 1 << 2,
-    Zero   = 1 << 6,
-    Sign   = 1 << 7,
+    Carry     = 1 << 0,
+    Overflow  = 1 << 1,
+    Parity    = 1 << 2,
+    AuxCarry  = 1 << 4,
+    Underflow = 1 << 5,
+    Zero      = 1 << 6,
+    Sign      = 1 << 7,
 }
 
 impl From<Flag> for u8 {
@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crate::memory::Memory;
+use crate::watch::{WatchEvent, Watches};
+
+// The game's init code writes init_value to the high score when it boots.
+// RAM is zero filled at power on, so the write itself, not the value, is
+// what shows the init code has run.
+pub struct HighScoreDescription {
+    pub rom:        &'static str,
+    pub address:    u16,
+    pub init_value: &'static [u8],
+}
+
+pub const GAMES: [HighScoreDescription; 1] = [
+    HighScoreDescription {
+        rom:        "space_invaders",
+        address:    0x20f4,
+        init_value: &[0x00, 0x00],
+    },
+];
+
+pub struct HighScore {
+    description: &'static HighScoreDescription,
+    path:        PathBuf,
+    saved:       Option<Vec<u8>>,
+    current:     Option<Vec<u8>>,
+}
+
+impl HighScore {
+    pub fn for_rom(rom_path: &str) -> Result<Option<HighScore>, Box<dyn Error>> {
+        let rom = Path::new(rom_path);
+        let name = rom.file_stem().and_then(|name| name.to_str());
+
+        let Some(description) = GAMES.iter().find(|game| Some(game.rom) == name) else {
+            return Ok(None);
+        };
+
+        let path  = rom.with_extension("hi");
+        let saved = match std::fs::read(&path) {
+            Ok(data) if data.len() == description.init_value.len() => Some(data),
+            Ok(_) => return Err(format!("{}: wrong size", path.display()).into()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        Ok(Some(HighScore {
+            description,
+            path,
+            saved,
+            current: None,
+        }))
+    }
+
+    pub fn watch(&self, watches: &mut Watches) {
+        watches.watch_write(self.last_address());
+    }
+
+    // Called with the watch events of each frame. Once the init code has
+    // cleared the high score, the best score known is put back in its place,
+    // so a watchdog reset does not lose it either.
+    pub fn update(&mut self, events: &[WatchEvent], memory: &mut Memory) {
+        let address   = usize::from(self.description.address);
+        let mut value = vec![0u8; self.description.init_value.len()];
+
+        memory.read(address, &mut value);
+
+        let initialised = events.iter().any(|event| {
+            matches!(event, WatchEvent::Write { address, .. } if *address == self.last_address())
+        });
+
+        if initialised && value == self.description.init_value {
+            if let Some(best) = self.current.as_ref().or(self.saved.as_ref()) {
+                memory.write(address, best);
+                value.copy_from_slice(best);
+            }
+            self.current = Some(value);
+        } else if self.current.is_some() {
+            self.current = Some(value);
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        match &self.current {
+            Some(current) if self.saved.as_ref() != Some(current) => {
+                std::fs::write(&self.path, current)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn last_address(&self) -> u16 {
+        self.description.address + self.description.init_value.len() as u16 - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLEAR: [WatchEvent; 1] = [WatchEvent::Write { address: 0x20f5, value: 0x00 }];
+
+    fn high_score(name: &str, saved: Option<&[u8]>) -> HighScore {
+        let directory = std::env::temp_dir().join(format!("high-score-{}-{}", name, std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let rom = directory.join("space_invaders");
+
+        if let Some(saved) = saved {
+            std::fs::write(rom.with_extension("hi"), saved).unwrap();
+        }
+        HighScore::for_rom(rom.to_str().unwrap()).unwrap().unwrap()
+    }
+
+    #[test]
+    fn unknown_roms_have_no_high_score() {
+        assert!(HighScore::for_rom("rom/galaxian").unwrap().is_none());
+    }
+
+    #[test]
+    fn saved_file_of_the_wrong_size_is_an_error() {
+        let directory = std::env::temp_dir().join(format!("high-score-size-{}", std::process::id()));
+
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("space_invaders.hi"), [1, 2, 3]).unwrap();
+
+        assert!(HighScore::for_rom(directory.join("space_invaders").to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn saved_score_is_injected_after_the_init_code_clears_it() {
+        let mut high_score = high_score("inject", Some(&[0x50, 0x12]));
+        let mut memory     = Memory::new();
+
+        high_score.update(&[], &mut memory);
+        assert_eq!(memory.read16(0x20f4), 0x0000);
+
+        high_score.update(&CLEAR, &mut memory);
+        assert_eq!(memory.read16(0x20f4), 0x1250);
+    }
+
+    #[test]
+    fn new_high_score_is_followed_and_survives_a_reset() {
+        let mut high_score = high_score("follow", Some(&[0x50, 0x12]));
+        let mut memory     = Memory::new();
+
+        high_score.update(&CLEAR, &mut memory);
+
+        memory.write16(0x20f4, 0x2000);
+        high_score.update(&[WatchEvent::Write { address: 0x20f5, value: 0x20 }], &mut memory);
+        assert_eq!(memory.read16(0x20f4), 0x2000);
+
+        memory.write16(0x20f4, 0x0000);
+        high_score.update(&CLEAR, &mut memory);
+        assert_eq!(memory.read16(0x20f4), 0x2000);
+    }
+
+    #[test]
+    fn save_writes_only_a_changed_score() {
+        let mut high_score = high_score("save", None);
+        let mut memory     = Memory::new();
+
+        high_score.save().unwrap();
+        assert!(!high_score.path.exists());
+
+        high_score.update(&CLEAR, &mut memory);
+        memory.write16(0x20f4, 0x0150);
+        high_score.update(&[], &mut memory);
+        high_score.save().unwrap();
+
+        assert_eq!(std::fs::read(&high_score.path).unwrap(), [0x50, 0x01]);
+        std::fs::remove_file(&high_score.path).unwrap();
+    }
+}
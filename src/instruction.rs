@@ -1,36 +1,40 @@
+use crate::cpu::Cpu;
+use crate::flag::Flag;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     Stc,
     Cmc,
-    Inr  { reg:   u8 },
-    Dcr  { reg:   u8 },
+    Inr  { reg:   Register },
+    Dcr  { reg:   Register },
     Cma,
     Daa,
     Nop,
-    Mov  { dst:   u8, src:   u8 },
-    Stax { rp:    u8 },
-    Ldax { rp:    u8 },
-    Add  { reg:   u8 },
-    Adc  { reg:   u8 },
-    Sub  { reg:   u8 },
-    Sbb  { reg:   u8 },
-    Ana  { reg:   u8 },
-    Xra  { reg:   u8 },
-    Ora  { reg:   u8 },
-    Cmp  { reg:   u8 },
+    Mov  { dst:   Register, src: Register },
+    Stax { rp:    RegisterPair },
+    Ldax { rp:    RegisterPair },
+    Add  { reg:   Register },
+    Adc  { reg:   Register },
+    Sub  { reg:   Register },
+    Sbb  { reg:   Register },
+    Ana  { reg:   Register },
+    Xra  { reg:   Register },
+    Ora  { reg:   Register },
+    Cmp  { reg:   Register },
     Rlc,
     Rrc,
     Ral,
     Rar,
-    Push { rp:    u8 },
-    Pop  { rp:    u8 },
-    Dad  { rp:    u8 },
-    Inx  { rp:    u8 },
-    Dcx  { rp:    u8 },
+    Push { rp:    RegisterPair },
+    Pop  { rp:    RegisterPair },
+    Dad  { rp:    RegisterPair },
+    Inx  { rp:    RegisterPair },
+    Dcx  { rp:    RegisterPair },
     Xchg,
     Xthl,
     Sphl,
-    Lxi  { rp:    u8, data: u16 },
-    Mvi  { reg:   u8, data: u8  },
+    Lxi  { rp:    RegisterPair, data: u16 },
+    Mvi  { reg:   Register, data: u8 },
     Adi  { data:  u8 },
     Aci  { data:  u8 },
     Sui  { data:  u8 },
@@ -77,4 +81,284 @@ pub enum Instruction {
     In   { exp:   u8 },
     Out  { exp:   u8 },
     Hlt,
+    Rim,
+    Sim,
+    Dsub,
+    Arhl,
+    Rdel,
+    Ldhi { data:  u8 },
+    Ldsi { data:  u8 },
+    Rstv,
+    Shlx,
+    Lhlx,
+    Jnk  { exp:  u16 },
+    Jk   { exp:  u16 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    M,
+    A,
+}
+
+impl From<u8> for Register {
+    fn from(code: u8) -> Register {
+        match code & 0b111 {
+            0b000 => Register::B,
+            0b001 => Register::C,
+            0b010 => Register::D,
+            0b011 => Register::E,
+            0b100 => Register::H,
+            0b101 => Register::L,
+            0b110 => Register::M,
+            0b111 => Register::A,
+            _     => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterPair {
+    B,
+    D,
+    H,
+    Sp,
+    Psw,
+}
+
+impl From<u8> for RegisterPair {
+    fn from(code: u8) -> RegisterPair {
+        match code {
+            0b000 => RegisterPair::B,
+            0b001 => RegisterPair::D,
+            0b010 => RegisterPair::H,
+            0b011 => RegisterPair::Sp,
+            0b100 => RegisterPair::Psw,
+            _     => unreachable!(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Sequential,
+    Call,
+    Return,
+    Push,
+    Pop,
+    Exchange,
+    LoadSp,
+}
+
+const ALL_FLAGS: u8 = Flag::Sign as u8 | Flag::Zero as u8 | Flag::AuxCarry as u8
+    | Flag::Parity as u8 | Flag::Carry as u8;
+
+const OVERFLOW_FLAGS: u8 = Flag::Overflow as u8 | Flag::Underflow as u8;
+
+#[allow(clippy::len_without_is_empty)]
+impl Instruction {
+    pub fn len(&self) -> u8 {
+        match self {
+            Instruction::Lxi  { .. } | Instruction::Sta  { .. } | Instruction::Lda  { .. }
+            | Instruction::Shld { .. } | Instruction::Lhld { .. } | Instruction::Jmp  { .. }
+            | Instruction::Jc   { .. } | Instruction::Jnc  { .. } | Instruction::Jz   { .. }
+            | Instruction::Jnz  { .. } | Instruction::Jm   { .. } | Instruction::Jp   { .. }
+            | Instruction::Jpe  { .. } | Instruction::Jpo  { .. } | Instruction::Call { .. }
+            | Instruction::Cc   { .. } | Instruction::Cnc  { .. } | Instruction::Cz   { .. }
+            | Instruction::Cnz  { .. } | Instruction::Cm   { .. } | Instruction::Cp   { .. }
+            | Instruction::Cpe  { .. } | Instruction::Cpo  { .. } | Instruction::Jnk  { .. }
+            | Instruction::Jk   { .. } => 3,
+            Instruction::Mvi  { .. } | Instruction::Adi  { .. } | Instruction::Aci  { .. }
+            | Instruction::Sui  { .. } | Instruction::Sbi  { .. } | Instruction::Ani  { .. }
+            | Instruction::Xri  { .. } | Instruction::Ori  { .. } | Instruction::Cpi  { .. }
+            | Instruction::In   { .. } | Instruction::Out  { .. } | Instruction::Ldhi { .. }
+            | Instruction::Ldsi { .. } => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn cycles(&self, cpu: Cpu, taken: bool) -> u8 {
+        match cpu {
+            Cpu::I8080 => self.cycles_8080(taken),
+            Cpu::I8085 => self.cycles_8085(taken),
+        }
+    }
+
+    fn cycles_8080(&self, taken: bool) -> u8 {
+        match self {
+            Instruction::Inr { reg } | Instruction::Dcr { reg } => {
+                if *reg == Register::M { 10 } else { 5 }
+            }
+            Instruction::Mov { dst, src } => {
+                if *dst == Register::M || *src == Register::M { 7 } else { 5 }
+            }
+            Instruction::Mvi { reg, .. } => {
+                if *reg == Register::M { 10 } else { 7 }
+            }
+            Instruction::Add { reg } | Instruction::Adc { reg } | Instruction::Sub { reg }
+            | Instruction::Sbb { reg } | Instruction::Ana { reg } | Instruction::Xra { reg }
+            | Instruction::Ora { reg } | Instruction::Cmp { reg } if *reg == Register::M => 7,
+            Instruction::Cc  { .. } | Instruction::Cnc { .. } | Instruction::Cz  { .. }
+            | Instruction::Cnz { .. } | Instruction::Cm  { .. } | Instruction::Cp  { .. }
+            | Instruction::Cpe { .. } | Instruction::Cpo { .. } => {
+                if taken { 17 } else { 11 }
+            }
+            Instruction::Rc  | Instruction::Rnc | Instruction::Rz  | Instruction::Rnz
+            | Instruction::Rm  | Instruction::Rp  | Instruction::Rpe | Instruction::Rpo => {
+                if taken { 11 } else { 5 }
+            }
+            Instruction::Stax { .. } | Instruction::Ldax { .. } | Instruction::Hlt
+            | Instruction::Adi  { .. } | Instruction::Aci  { .. } | Instruction::Sui  { .. }
+            | Instruction::Sbi  { .. } | Instruction::Ani  { .. } | Instruction::Xri  { .. }
+            | Instruction::Ori  { .. } | Instruction::Cpi  { .. } => 7,
+            Instruction::Pop  { .. } | Instruction::Dad  { .. } | Instruction::Lxi  { .. }
+            | Instruction::Jmp  { .. } | Instruction::Jc   { .. } | Instruction::Jnc  { .. }
+            | Instruction::Jz   { .. } | Instruction::Jnz  { .. } | Instruction::Jm   { .. }
+            | Instruction::Jp   { .. } | Instruction::Jpe  { .. } | Instruction::Jpo  { .. }
+            | Instruction::Ret | Instruction::In { .. } | Instruction::Out { .. } => 10,
+            Instruction::Push { .. } | Instruction::Rst  { .. } => 11,
+            Instruction::Inx  { .. } | Instruction::Dcx  { .. } | Instruction::Sphl
+            | Instruction::Pchl => 5,
+            Instruction::Sta  { .. } | Instruction::Lda  { .. } => 13,
+            Instruction::Shld { .. } | Instruction::Lhld { .. } => 16,
+            Instruction::Call { .. } => 17,
+            Instruction::Xthl => 18,
+            _ => 4,
+        }
+    }
+
+    fn cycles_8085(&self, taken: bool) -> u8 {
+        match self {
+            Instruction::Inr { reg } | Instruction::Dcr { reg } => {
+                if *reg == Register::M { 10 } else { 4 }
+            }
+            Instruction::Mov { dst, src } => {
+                if *dst == Register::M || *src == Register::M { 7 } else { 4 }
+            }
+            Instruction::Jc  { .. } | Instruction::Jnc { .. } | Instruction::Jz  { .. }
+            | Instruction::Jnz { .. } | Instruction::Jm  { .. } | Instruction::Jp  { .. }
+            | Instruction::Jpe { .. } | Instruction::Jpo { .. } | Instruction::Jnk { .. }
+            | Instruction::Jk  { .. } => {
+                if taken { 10 } else { 7 }
+            }
+            Instruction::Cc  { .. } | Instruction::Cnc { .. } | Instruction::Cz  { .. }
+            | Instruction::Cnz { .. } | Instruction::Cm  { .. } | Instruction::Cp  { .. }
+            | Instruction::Cpe { .. } | Instruction::Cpo { .. } => {
+                if taken { 18 } else { 9 }
+            }
+            Instruction::Rc  | Instruction::Rnc | Instruction::Rz  | Instruction::Rnz
+            | Instruction::Rm  | Instruction::Rp  | Instruction::Rpe | Instruction::Rpo
+            | Instruction::Rstv => {
+                if taken { 12 } else { 6 }
+            }
+            Instruction::Inx  { .. } | Instruction::Dcx  { .. } | Instruction::Sphl
+            | Instruction::Pchl => 6,
+            Instruction::Push { .. } | Instruction::Rst  { .. } => 12,
+            Instruction::Dsub | Instruction::Rdel | Instruction::Ldhi { .. }
+            | Instruction::Ldsi { .. } | Instruction::Shlx | Instruction::Lhlx => 10,
+            Instruction::Xthl => 16,
+            Instruction::Call { .. } => 18,
+            Instruction::Arhl => 7,
+            Instruction::Hlt => 5,
+            _ => self.cycles_8080(taken),
+        }
+    }
+
+    pub fn flags_written(&self, cpu: Cpu) -> u8 {
+        match cpu {
+            Cpu::I8080 => self.flags_written_8080(),
+            Cpu::I8085 => self.flags_written_8085(),
+        }
+    }
+
+    fn flags_written_8080(&self) -> u8 {
+        match self {
+            Instruction::Stc | Instruction::Cmc | Instruction::Rlc | Instruction::Rrc
+            | Instruction::Ral | Instruction::Rar | Instruction::Dad { .. } => Flag::Carry as u8,
+            Instruction::Inr { .. } | Instruction::Dcr { .. } => ALL_FLAGS & !(Flag::Carry as u8),
+            Instruction::Daa
+            | Instruction::Add { .. } | Instruction::Adc { .. } | Instruction::Sub { .. }
+            | Instruction::Sbb { .. } | Instruction::Ana { .. } | Instruction::Xra { .. }
+            | Instruction::Ora { .. } | Instruction::Cmp { .. }
+            | Instruction::Adi { .. } | Instruction::Aci { .. } | Instruction::Sui { .. }
+            | Instruction::Sbi { .. } | Instruction::Ani { .. } | Instruction::Xri { .. }
+            | Instruction::Ori { .. } | Instruction::Cpi { .. }
+            | Instruction::Pop { rp: RegisterPair::Psw } => ALL_FLAGS,
+            Instruction::Dsub => ALL_FLAGS | OVERFLOW_FLAGS,
+            Instruction::Rdel => Flag::Carry as u8 | Flag::Overflow as u8,
+            Instruction::Arhl => Flag::Carry as u8,
+            _ => 0,
+        }
+    }
+
+    // The 8085 also updates V and K after ALU operations and K when INX or
+    // DCX wraps, and keeps both in the flags POP PSW loads.
+    fn flags_written_8085(&self) -> u8 {
+        match self {
+            Instruction::Add { .. } | Instruction::Adc { .. } | Instruction::Sub { .. }
+            | Instruction::Sbb { .. } | Instruction::Ana { .. } | Instruction::Xra { .. }
+            | Instruction::Ora { .. } | Instruction::Cmp { .. }
+            | Instruction::Adi { .. } | Instruction::Aci { .. } | Instruction::Sui { .. }
+            | Instruction::Sbi { .. } | Instruction::Ani { .. } | Instruction::Xri { .. }
+            | Instruction::Ori { .. } | Instruction::Cpi { .. }
+            | Instruction::Pop { rp: RegisterPair::Psw } => ALL_FLAGS | OVERFLOW_FLAGS,
+            Instruction::Inx { .. } | Instruction::Dcx { .. } => Flag::Underflow as u8,
+            _ => self.flags_written_8080(),
+        }
+    }
+
+    pub fn reads_memory(&self) -> bool {
+        match self {
+            Instruction::Inr { reg } | Instruction::Dcr { reg }
+            | Instruction::Add { reg } | Instruction::Adc { reg } | Instruction::Sub { reg }
+            | Instruction::Sbb { reg } | Instruction::Ana { reg } | Instruction::Xra { reg }
+            | Instruction::Ora { reg } | Instruction::Cmp { reg } => *reg == Register::M,
+            Instruction::Mov { src, .. } => *src == Register::M,
+            Instruction::Ldax { .. } | Instruction::Pop { .. } | Instruction::Xthl
+            | Instruction::Lda  { .. } | Instruction::Lhld { .. }
+            | Instruction::Ret | Instruction::Rc  | Instruction::Rnc | Instruction::Rz
+            | Instruction::Rnz | Instruction::Rm  | Instruction::Rp  | Instruction::Rpe
+            | Instruction::Rpo | Instruction::Lhlx => true,
+            _ => false,
+        }
+    }
+
+    pub fn writes_memory(&self) -> bool {
+        match self {
+            Instruction::Inr { reg } | Instruction::Dcr { reg }
+            | Instruction::Mov { dst: reg, .. } | Instruction::Mvi { reg, .. } => *reg == Register::M,
+            Instruction::Stax { .. } | Instruction::Push { .. } | Instruction::Xthl
+            | Instruction::Sta  { .. } | Instruction::Shld { .. } | Instruction::Rst { .. }
+            | Instruction::Call { .. } | Instruction::Cc   { .. } | Instruction::Cnc { .. }
+            | Instruction::Cz   { .. } | Instruction::Cnz  { .. } | Instruction::Cm  { .. }
+            | Instruction::Cp   { .. } | Instruction::Cpe  { .. } | Instruction::Cpo { .. }
+            | Instruction::Shlx | Instruction::Rstv => true,
+            _ => false,
+        }
+    }
+
+    // How the instruction uses the stack. Conditional calls and returns
+    // report Call and Return whether or not the condition holds.
+    pub fn flow(&self) -> Flow {
+        match self {
+            Instruction::Call { .. } | Instruction::Cc   { .. } | Instruction::Cnc { .. }
+            | Instruction::Cz   { .. } | Instruction::Cnz  { .. } | Instruction::Cm  { .. }
+            | Instruction::Cp   { .. } | Instruction::Cpe  { .. } | Instruction::Cpo { .. }
+            | Instruction::Rst  { .. } | Instruction::Rstv => Flow::Call,
+            Instruction::Ret | Instruction::Rc  | Instruction::Rnc | Instruction::Rz
+            | Instruction::Rnz | Instruction::Rm  | Instruction::Rp  | Instruction::Rpe
+            | Instruction::Rpo => Flow::Return,
+            Instruction::Push { .. } => Flow::Push,
+            Instruction::Pop  { .. } => Flow::Pop,
+            Instruction::Xthl => Flow::Exchange,
+            Instruction::Sphl | Instruction::Lxi { rp: RegisterPair::Sp, .. } => Flow::LoadSp,
+            _ => Flow::Sequential,
+        }
+    }
 }
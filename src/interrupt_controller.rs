@@ -0,0 +1,100 @@
+use std::collections::VecDeque;
+use std::error::Error;
+
+use crate::snapshot::{Reader, Writer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptSource {
+    Rst(u8),
+    Instruction([u8; 3]),
+}
+
+impl InterruptSource {
+    pub fn bytes(&self) -> [u8; 3] {
+        match *self {
+            InterruptSource::Rst(number)        => [0b11000111 | ((number & 0b111) << 3), 0, 0],
+            InterruptSource::Instruction(bytes) => bytes,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct InterruptController {
+    pending:  VecDeque<InterruptSource>,
+    ei_delay: bool,
+}
+
+impl InterruptController {
+    pub fn new() -> InterruptController {
+        InterruptController {
+            pending:  VecDeque::new(),
+            ei_delay: false,
+        }
+    }
+
+    // A source that is already waiting is not queued twice, the same way a
+    // held interrupt line is only acknowledged once.
+    pub fn request(&mut self, source: InterruptSource) {
+        if !self.pending.contains(&source) {
+            self.pending.push_back(source);
+        }
+    }
+
+    pub fn delay(&mut self) {
+        self.ei_delay = true;
+    }
+
+    // Called once at every instruction boundary. The boundary right after
+    // EI is skipped, so the instruction following EI always runs first.
+    pub fn ready(&mut self, inte: bool) -> bool {
+        if self.ei_delay {
+            self.ei_delay = false;
+            return false;
+        }
+        inte
+    }
+
+    pub fn acknowledge(&mut self) -> Option<InterruptSource> {
+        self.pending.pop_front()
+    }
+
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.bool(self.ei_delay);
+        state.u64(self.pending.len() as u64);
+
+        for source in &self.pending {
+            match *source {
+                InterruptSource::Rst(number) => {
+                    state.u8(0);
+                    state.u8(number);
+                }
+                InterruptSource::Instruction(bytes) => {
+                    state.u8(1);
+                    bytes.iter().for_each(|byte| state.u8(*byte));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader) -> Result<(), Box<dyn Error>> {
+        self.ei_delay = state.bool()?;
+        self.pending.clear();
+
+        for _ in 0..state.u64()? {
+            let source = match state.u8()? {
+                0 => InterruptSource::Rst(state.u8()?),
+                1 => InterruptSource::Instruction([state.u8()?, state.u8()?, state.u8()?]),
+                _ => return Err("saved state has an unknown interrupt".into()),
+            };
+
+            self.pending.push_back(source);
+        }
+        Ok(())
+    }
+}
+
+impl Default for InterruptController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,168 @@
+use std::error::Error;
+
+use crate::snapshot::{Reader, Writer};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterruptLine {
+    Trap,
+    Rst55,
+    Rst65,
+    Rst75,
+}
+
+const MASK_55: u8 = 1 << 0;
+const MASK_65: u8 = 1 << 1;
+const MASK_75: u8 = 1 << 2;
+
+const SIM_MASK_ENABLE: u8 = 1 << 3;
+const SIM_RESET_75:    u8 = 1 << 4;
+const SIM_SOD_ENABLE:  u8 = 1 << 6;
+
+// The 8085 interrupt inputs. TRAP is edge and level triggered and cannot be
+// masked, RST 7.5 latches a rising edge, RST 6.5 and 5.5 follow the level.
+#[derive(Clone)]
+pub struct InterruptLines {
+    trap:       bool,
+    rst55:      bool,
+    rst65:      bool,
+    rst75:      bool,
+    trap_edge:  bool,
+    rst75_edge: bool,
+    masks:      u8,
+    trap_inte:  Option<bool>,
+    sid:        bool,
+    sod:        bool,
+}
+
+impl InterruptLines {
+    pub fn new() -> InterruptLines {
+        InterruptLines {
+            trap:       false,
+            rst55:      false,
+            rst65:      false,
+            rst75:      false,
+            trap_edge:  false,
+            rst75_edge: false,
+            masks:      MASK_55 | MASK_65 | MASK_75,
+            trap_inte:  None,
+            sid:        false,
+            sod:        false,
+        }
+    }
+
+    pub fn set(&mut self, line: InterruptLine, level: bool) {
+        match line {
+            InterruptLine::Trap => {
+                self.trap_edge |= level && !self.trap;
+                self.trap       = level;
+            }
+            InterruptLine::Rst55 => self.rst55 = level,
+            InterruptLine::Rst65 => self.rst65 = level,
+            InterruptLine::Rst75 => {
+                self.rst75_edge |= level && !self.rst75;
+                self.rst75       = level;
+            }
+        }
+    }
+
+    pub fn set_sid(&mut self, level: bool) {
+        self.sid = level;
+    }
+
+    pub fn sod(&self) -> bool {
+        self.sod
+    }
+
+    // Returns the vector of the highest priority interrupt to take at this
+    // instruction boundary. `ready` is false while interrupts are disabled
+    // or the instruction after EI is still pending.
+    pub fn acknowledge(&mut self, ready: bool, inte: bool) -> Option<u16> {
+        if self.trap_edge && self.trap {
+            self.trap_edge = false;
+            self.trap_inte = Some(inte);
+            return Some(0x24);
+        }
+
+        if !ready {
+            return None;
+        }
+
+        if self.rst75_edge && self.masks & MASK_75 == 0 {
+            self.rst75_edge = false;
+            Some(0x3c)
+        } else if self.rst65 && self.masks & MASK_65 == 0 {
+            Some(0x34)
+        } else if self.rst55 && self.masks & MASK_55 == 0 {
+            Some(0x2c)
+        } else {
+            None
+        }
+    }
+
+    pub fn rim(&mut self, inte: bool) -> u8 {
+        let inte = self.trap_inte.take().unwrap_or(inte);
+
+        self.masks
+            | u8::from(inte)             << 3
+            | u8::from(self.rst55)       << 4
+            | u8::from(self.rst65)       << 5
+            | u8::from(self.rst75_edge)  << 6
+            | u8::from(self.sid)         << 7
+    }
+
+    pub fn sim(&mut self, value: u8) {
+        if value & SIM_MASK_ENABLE != 0 {
+            self.masks = value & (MASK_55 | MASK_65 | MASK_75);
+        }
+
+        if value & SIM_RESET_75 != 0 {
+            self.rst75_edge = false;
+        }
+
+        if value & SIM_SOD_ENABLE != 0 {
+            self.sod = value & 0x80 != 0;
+        }
+    }
+
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.bool(self.trap);
+        state.bool(self.rst55);
+        state.bool(self.rst65);
+        state.bool(self.rst75);
+        state.bool(self.trap_edge);
+        state.bool(self.rst75_edge);
+        state.u8(self.masks);
+        state.u8(match self.trap_inte {
+            None        => 0,
+            Some(false) => 1,
+            Some(true)  => 2,
+        });
+        state.bool(self.sid);
+        state.bool(self.sod);
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader) -> Result<(), Box<dyn Error>> {
+        self.trap       = state.bool()?;
+        self.rst55      = state.bool()?;
+        self.rst65      = state.bool()?;
+        self.rst75      = state.bool()?;
+        self.trap_edge  = state.bool()?;
+        self.rst75_edge = state.bool()?;
+        self.masks      = state.u8()? & (MASK_55 | MASK_65 | MASK_75);
+        self.trap_inte  = match state.u8()? {
+            0 => None,
+            1 => Some(false),
+            2 => Some(true),
+            _ => return Err("saved state is corrupt".into()),
+        };
+        self.sid        = state.bool()?;
+        self.sod        = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for InterruptLines {
+    fn default() -> Self {
+        Self::new()
+    }
+}
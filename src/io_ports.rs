@@ -1,7 +1,15 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::snapshot::{Reader, Writer};
+
+#[derive(Clone)]
 pub struct IOPorts {
     input:          InputPorts,
     output:         OutputPorts,
     shift_register: u16,
+    stub:           Option<Box<[u8; 256]>>,
 }
 
 impl IOPorts {
@@ -10,10 +18,24 @@ impl IOPorts {
             input:          InputPorts::new(),
             output:         OutputPorts::new(),
             shift_register: 0,
+            stub:           None,
+        }
+    }
+
+    // Ports without the board behind them, for CPU tests. Every port is a
+    // latch that reads back the last value set or written.
+    pub fn stub() -> IOPorts {
+        IOPorts {
+            stub: Some(Box::new([0; 256])),
+            ..IOPorts::new()
         }
     }
 
     pub fn read(&self, port: u8) -> u8 {
+        if let Some(ports) = &self.stub {
+            return ports[usize::from(port)];
+        }
+
         match port {
             0 => self.input.input0,
             1 => self.input.input1,
@@ -28,6 +50,11 @@ impl IOPorts {
     }
 
     pub fn write(&mut self, port: u8, value: u8) {
+        if let Some(ports) = &mut self.stub {
+            ports[usize::from(port)] = value;
+            return;
+        }
+
         match port {
             2 => self.output.shift_amount = value & 0b111,
             3 => self.output.sound1 = value,
@@ -40,8 +67,139 @@ impl IOPorts {
             _ => panic!("invalid write port"),
         }
     }
+
+    // The inputs are switches on the cabinet and keep their state.
+    pub fn reset(&mut self) {
+        self.output         = OutputPorts::new();
+        self.shift_register = 0;
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let (port, bit) = button.port_bit();
+
+        let input = match port {
+            1 => &mut self.input.input1,
+            2 => &mut self.input.input2,
+            _ => unreachable!(),
+        };
+
+        if pressed {
+            *input |=  1 << bit;
+        } else {
+            *input &= !(1 << bit);
+        }
+    }
+
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.u8(self.input.input0);
+        state.u8(self.input.input1);
+        state.u8(self.input.input2);
+        state.u8(self.output.shift_amount);
+        state.u8(self.output.sound1);
+        state.u8(self.output.sound2);
+        state.u8(self.output.watchdog);
+        state.u16(self.shift_register);
+        state.bool(self.stub.is_some());
+
+        if let Some(ports) = &self.stub {
+            state.bytes(&ports[..]);
+        }
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader) -> Result<(), Box<dyn Error>> {
+        self.input.input0         = state.u8()?;
+        self.input.input1         = state.u8()?;
+        self.input.input2         = state.u8()?;
+        self.output.shift_amount  = state.u8()? & 0b111;
+        self.output.sound1        = state.u8()?;
+        self.output.sound2        = state.u8()?;
+        self.output.watchdog      = state.u8()?;
+        self.shift_register       = state.u16()?;
+        self.stub                 = match state.bool()? {
+            true  => Some(Box::new(state.bytes()?.try_into()?)),
+            false => None,
+        };
+        Ok(())
+    }
+}
+
+impl Default for IOPorts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Coin,
+    P1Start,
+    P2Start,
+    P1Fire,
+    P1Left,
+    P1Right,
+    P2Fire,
+    P2Left,
+    P2Right,
+    Tilt,
+}
+
+impl Button {
+    fn port_bit(self) -> (u8, u8) {
+        match self {
+            Button::Coin    => (1, 0),
+            Button::P2Start => (1, 1),
+            Button::P1Start => (1, 2),
+            Button::P1Fire  => (1, 4),
+            Button::P1Left  => (1, 5),
+            Button::P1Right => (1, 6),
+            Button::Tilt    => (2, 2),
+            Button::P2Fire  => (2, 4),
+            Button::P2Left  => (2, 5),
+            Button::P2Right => (2, 6),
+        }
+    }
+}
+
+impl FromStr for Button {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Button, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "coin"    => Ok(Button::Coin),
+            "p1start" => Ok(Button::P1Start),
+            "p2start" => Ok(Button::P2Start),
+            "p1fire"  => Ok(Button::P1Fire),
+            "p1left"  => Ok(Button::P1Left),
+            "p1right" => Ok(Button::P1Right),
+            "p2fire"  => Ok(Button::P2Fire),
+            "p2left"  => Ok(Button::P2Left),
+            "p2right" => Ok(Button::P2Right),
+            "tilt"    => Ok(Button::Tilt),
+            _         => Err(format!("unknown button {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Button {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Button::Coin    => "coin",
+            Button::P1Start => "p1start",
+            Button::P2Start => "p2start",
+            Button::P1Fire  => "p1fire",
+            Button::P1Left  => "p1left",
+            Button::P1Right => "p1right",
+            Button::P2Fire  => "p2fire",
+            Button::P2Left  => "p2left",
+            Button::P2Right => "p2right",
+            Button::Tilt    => "tilt",
+        };
+
+        f.write_str(name)
+    }
 }
 
+#[derive(Clone)]
 struct InputPorts {
     input0: u8,
     input1: u8,
@@ -58,6 +216,7 @@ impl InputPorts {
     }
 }
 
+#[derive(Clone)]
 struct OutputPorts {
     shift_amount: u8,
     sound1:       u8,
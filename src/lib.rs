@@ -0,0 +1,39 @@
+pub mod batch;
+pub mod call_stack;
+mod checksum;
+pub mod cheats;
+pub mod coverage;
+pub mod cpu;
+mod cpu_state;
+pub mod decoder;
+mod disassembler;
+mod dispatch;
+pub mod emulator;
+pub mod env;
+pub mod flag;
+pub mod high_score;
+pub mod instruction;
+pub mod interrupt_controller;
+pub mod interrupt_lines;
+pub mod memory;
+pub mod patch;
+mod png;
+mod pointer_register;
+pub mod profiler;
+pub mod ram_search;
+pub mod io_ports;
+mod program_state_word;
+#[cfg(feature = "python")]
+mod python;
+mod register_pair;
+pub mod registers;
+pub mod replay;
+pub mod scheduler;
+mod snapshot;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod statistics;
+pub mod symbols;
+pub mod video;
+pub mod watch;
+pub mod watchdog;
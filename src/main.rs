@@ -1,26 +1,128 @@
-#![feature(bigint_helper_methods)]
-
 use std::error::Error;
-use emulator::Emulator;
-
-mod cpu_state;
-mod decoder;
-mod disassembler;
-mod emulator;
-mod flag;
-mod instruction;
-mod interrupt_timer;
-mod memory;
-mod pointer_register;
-mod io_ports;
-mod program_state_word;
-mod register_pair;
+use std::path::Path;
+
+use space_invaders::cheats::Cheats;
+use space_invaders::emulator::Emulator;
+use space_invaders::high_score::HighScore;
+use space_invaders::patch;
+use space_invaders::replay::Replay;
+use space_invaders::symbols::Symbols;
+
+mod bench;
+mod search;
+mod terminal;
+
+const ROM:     &str = "./rom/space_invaders";
+const ROM_END: u16  = 0x2000;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let mut emulator = Emulator::new();
+    let args: Vec<String>  = std::env::args().skip(1).collect();
+    let patches: Vec<&str> = args.iter().filter_map(|arg| arg.strip_prefix("--patch=")).collect();
+    let mut emulator       = Emulator::new();
+
+    emulator.load_rom(ROM, &patches)?;
+    emulator.set_watchdog(!args.iter().any(|arg| arg == "--no-watchdog"));
+    emulator.set_strict(args.iter().any(|arg| arg == "--strict"));
+
+    if args.iter().any(|arg| arg == "--coverage") {
+        emulator.enable_coverage();
+    }
+    if args.iter().any(|arg| arg == "--profile") {
+        emulator.enable_profiler();
+    }
+
+    emulator.set_symbols(Symbols::load(&Path::new(ROM).with_extension("sym"))?);
+
+    if let Some(path) = args.iter().find_map(|arg| arg.strip_prefix("--cheats=")) {
+        emulator.set_cheats(Cheats::load(Path::new(path))?)?;
+    }
+
+    for breakpoint in args.iter().filter_map(|arg| arg.strip_prefix("--break=")) {
+        let address = match emulator.symbols().lookup(breakpoint) {
+            Some(address) => address,
+            None          => u16::from_str_radix(breakpoint.trim_start_matches("0x"), 16)
+                .map_err(|_| format!("unknown breakpoint {}", breakpoint))?,
+        };
+
+        emulator.add_breakpoint(address);
+    }
+
+    let mut commands = args.iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .map(String::as_str);
+
+    let result = match commands.next() {
+        Some("bench") => bench::run(&mut emulator, commands.next()),
+        Some("search") => search::run(&mut emulator),
+        Some("script") => match commands.next() {
+            Some(path) => run_script(&mut emulator, Path::new(path)),
+            None       => Err("usage: script FILE".into()),
+        },
+        Some("ips") => match (commands.next(), commands.next(), commands.next()) {
+            (Some(original), Some(modified), Some(output)) => {
+                let patch = patch::create_ips(&std::fs::read(original)?, &std::fs::read(modified)?)?;
 
-    emulator.load_rom("./rom/space_invaders")?;
-    emulator.run()?;
+                std::fs::write(output, patch).map_err(Into::into)
+            }
+            _ => Err("usage: ips ORIGINAL MODIFIED OUTPUT".into()),
+        },
+        Some("terminal") => {
+            let mut high_score = HighScore::for_rom(ROM)?;
+            let playback       = args.iter()
+                .find_map(|arg| arg.strip_prefix("--replay="))
+                .map(|path| Replay::load(Path::new(path)))
+                .transpose()?;
+            let result = terminal::run(&mut emulator, high_score.as_mut(), playback.as_ref());
 
+            if let Some(high_score) = &high_score {
+                high_score.save()?;
+            }
+            match (result, args.iter().find_map(|arg| arg.strip_prefix("--record="))) {
+                (Ok(recording), Some(path)) => std::fs::write(path, recording.to_string()).map_err(Into::into),
+                (result, _)                 => result.map(|_| ()),
+            }
+        }
+        _ => {
+            emulator.set_tracer(Some(|address, line| println!("{:04x}  {}", address, line)));
+            emulator.run()
+        }
+    };
+
+    // Coverage and the profile are written even when the run failed, and
+    // the run's own error is the one reported.
+    let coverage = write_coverage(&emulator);
+    let profile  = write_profile(&emulator);
+
+    if result.is_err() {
+        eprintln!("backtrace:\n{}", emulator.backtrace());
+    }
+
+    result.and(coverage).and(profile)
+}
+
+fn write_coverage(emulator: &Emulator) -> Result<(), Box<dyn Error>> {
+    if let Some(coverage) = emulator.coverage() {
+        std::fs::write("coverage.json", coverage.to_json())?;
+        std::fs::write("coverage.png", coverage.heatmap())?;
+        std::fs::write("coverage.lst", coverage.listing(emulator.cpu(), emulator.memory(), 0, ROM_END)?)?;
+    }
+    Ok(())
+}
+
+fn write_profile(emulator: &Emulator) -> Result<(), Box<dyn Error>> {
+    if let Some(profiler) = emulator.profiler() {
+        std::fs::write("profile.txt", profiler.report())?;
+        std::fs::write("profile.folded", profiler.folded())?;
+    }
     Ok(())
 }
+
+#[cfg(feature = "scripting")]
+fn run_script(emulator: &mut Emulator, path: &Path) -> Result<(), Box<dyn Error>> {
+    space_invaders::script::run_file(emulator, path)
+}
+
+#[cfg(not(feature = "scripting"))]
+fn run_script(_: &mut Emulator, _: &Path) -> Result<(), Box<dyn Error>> {
+    Err("built without scripting, rebuild with --features scripting".into())
+}
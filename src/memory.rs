@@ -1,16 +1,40 @@
+const ADDRESS_SPACE: usize = 0x10000;
+
+#[derive(Clone)]
 pub struct Memory {
     pub memory: Vec<u8>,
 }
 
 impl Memory {
     pub fn new() -> Memory {
+        Memory::with_size(0x5000)
+    }
+
+    pub fn with_size(size: usize) -> Memory {
         Memory {
-            memory: vec![0; 0x5000],
+            memory: vec![0; size],
         }
     }
 
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+
+    // On a fully populated 64K machine accesses that run past 0xffff wrap
+    // around to 0x0000 like the 16-bit address bus. Smaller memories have
+    // nothing mapped past their end.
     pub fn read(&self, address: usize, buf: &mut [u8]) {
-        buf.copy_from_slice(&self.memory[address..address + buf.len()]);
+        if self.wraps(address, buf.len()) {
+            for (offset, byte) in buf.iter_mut().enumerate() {
+                *byte = self.memory[(address + offset) & 0xffff];
+            }
+        } else {
+            buf.copy_from_slice(&self.memory[address..address + buf.len()]);
+        }
     }
 
     pub fn read8(&self, address: u16) -> u8 {
@@ -28,7 +52,13 @@ impl Memory {
     }
 
     pub fn write(&mut self, address: usize, buf: &[u8]) {
-        self.memory[address..address + buf.len()].copy_from_slice(buf);
+        if self.wraps(address, buf.len()) {
+            for (offset, byte) in buf.iter().enumerate() {
+                self.memory[(address + offset) & 0xffff] = *byte;
+            }
+        } else {
+            self.memory[address..address + buf.len()].copy_from_slice(buf);
+        }
     }
 
     pub fn write8(&mut self, address: u16, data: u8) {
@@ -38,4 +68,30 @@ impl Memory {
     pub fn write16(&mut self, address: u16, data: u16) {
         self.write(address.into(), &data.to_le_bytes());
     }
+
+    fn wraps(&self, address: usize, len: usize) -> bool {
+        self.memory.len() == ADDRESS_SPACE && address + len > ADDRESS_SPACE
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_full_address_space_wraps() {
+        let mut memory = Memory::with_size(ADDRESS_SPACE);
+
+        memory.write(0xffff, &[0x34, 0x12]);
+
+        assert_eq!(memory.read8(0x0000), 0x12);
+        assert_eq!(memory.read16(0xffff), 0x1234);
+        assert!(!Memory::new().wraps(0x4fff, 2));
+    }
 }
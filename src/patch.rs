@@ -0,0 +1,367 @@
+use std::error::Error;
+
+use crate::checksum::crc32;
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_EOF:    &[u8] = b"EOF";
+const BPS_HEADER: &[u8] = b"BPS1";
+
+// An IPS record offset that reads as "EOF" would end the patch early.
+const IPS_EOF_OFFSET: usize = 0x454f46;
+const IPS_MAX_SIZE:   usize = 0x1000000;
+const IPS_MAX_RECORD: usize = 0xffff;
+
+struct Reader<'a> {
+    data:     &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader {
+            data,
+            position: 0,
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.position
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if length > self.remaining() {
+            return Err("patch is truncated".into());
+        }
+        self.position += length;
+        Ok(&self.data[self.position - length..self.position])
+    }
+
+    fn byte(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn big_endian(&mut self, length: usize) -> Result<usize, Box<dyn Error>> {
+        Ok(self.take(length)?.iter().fold(0, |value, byte| value << 8 | usize::from(*byte)))
+    }
+
+    fn varint(&mut self) -> Result<usize, Box<dyn Error>> {
+        let mut value: usize = 0;
+        let mut shift: usize = 1;
+
+        loop {
+            let byte = self.byte()?;
+
+            value = usize::from(byte & 0x7f).checked_mul(shift)
+                .and_then(|part| value.checked_add(part))
+                .ok_or("patch number is too large")?;
+            if byte & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or("patch number is too large")?;
+            value = value.checked_add(shift).ok_or("patch number is too large")?;
+        }
+    }
+}
+
+// Picks the format from the header.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if patch.starts_with(IPS_HEADER) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_HEADER) {
+        apply_bps(rom, patch)
+    } else {
+        Err("not an IPS or BPS patch".into())
+    }
+}
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut reader = Reader::new(patch);
+    let mut target = rom.to_vec();
+
+    if reader.take(IPS_HEADER.len())? != IPS_HEADER {
+        return Err("missing IPS header".into());
+    }
+
+    loop {
+        if reader.data[reader.position..].starts_with(IPS_EOF) {
+            reader.take(IPS_EOF.len())?;
+
+            // Some patches shrink the image with a 24-bit length after EOF.
+            if reader.remaining() == 3 {
+                target.truncate(reader.big_endian(3)?);
+            }
+            return Ok(target);
+        }
+
+        let offset = reader.big_endian(3)?;
+        let size   = reader.big_endian(2)?;
+
+        let bytes = if size == 0 {
+            let count = reader.big_endian(2)?;
+
+            vec![reader.byte()?; count]
+        } else {
+            reader.take(size)?.to_vec()
+        };
+
+        if target.len() < offset + bytes.len() {
+            target.resize(offset + bytes.len(), 0);
+        }
+        target[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+}
+
+// The source, target and patch checksums are all checked, so a BPS patch
+// is only applied to the exact image it was made for.
+pub fn apply_bps(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if patch.len() < BPS_HEADER.len() + 12 {
+        return Err("patch is truncated".into());
+    }
+
+    let footer   = patch.len() - 12;
+    let checksum = |offset: usize| {
+        u32::from_le_bytes([patch[offset], patch[offset + 1], patch[offset + 2], patch[offset + 3]])
+    };
+
+    if crc32(&patch[..footer + 8]) != checksum(footer + 8) {
+        return Err("BPS patch checksum mismatch".into());
+    }
+    if crc32(source) != checksum(footer) {
+        return Err("BPS patch was made for a different ROM".into());
+    }
+
+    let mut reader = Reader::new(&patch[..footer]);
+
+    reader.take(BPS_HEADER.len())?;
+
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata    = reader.varint()?;
+
+    reader.take(metadata)?;
+
+    if source_size != source.len() {
+        return Err("BPS patch was made for a different ROM size".into());
+    }
+
+    // The sizes come from the patch, so neither the allocation nor any
+    // action may trust them further than the bytes actually produced.
+    let mut target          = Vec::with_capacity(target_size.min(source.len().saturating_mul(4)));
+    let mut source_relative = 0usize;
+    let mut target_relative = 0usize;
+    let relative            = |base: usize, offset: usize| {
+        let distance = offset >> 1;
+        let moved    = if offset & 1 != 0 {
+            base.checked_sub(distance)
+        } else {
+            base.checked_add(distance)
+        };
+
+        moved.ok_or("BPS copy offset out of range")
+    };
+
+    while reader.remaining() > 0 {
+        let data   = reader.varint()?;
+        let length = (data >> 2) + 1;
+
+        if length > target_size - target.len() {
+            return Err("BPS patch writes past the target size".into());
+        }
+
+        match data & 0b11 {
+            0 => {
+                let start = target.len();
+                let bytes = source.get(start..start + length).ok_or("BPS read past the source")?;
+
+                target.extend_from_slice(bytes);
+            }
+            1 => target.extend_from_slice(reader.take(length)?),
+            2 => {
+                source_relative = relative(source_relative, reader.varint()?)?;
+
+                let bytes = source_relative.checked_add(length)
+                    .and_then(|end| source.get(source_relative..end))
+                    .ok_or("BPS copy past the source")?;
+
+                target.extend_from_slice(bytes);
+                source_relative += length;
+            }
+            _ => {
+                target_relative = relative(target_relative, reader.varint()?)?;
+
+                // The copy may overlap the bytes it is producing.
+                for _ in 0..length {
+                    let byte = *target.get(target_relative).ok_or("BPS copy past the target")?;
+
+                    target.push(byte);
+                    target_relative += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != checksum(footer + 4) {
+        return Err("BPS patch produced the wrong ROM".into());
+    }
+    Ok(target)
+}
+
+fn u24(value: usize) -> [u8; 3] {
+    let [_, high, middle, low] = (value as u32).to_be_bytes();
+
+    [high, middle, low]
+}
+
+pub fn create_ips(original: &[u8], modified: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    if modified.len() > IPS_MAX_SIZE {
+        return Err("IPS patches cannot address more than 16MB".into());
+    }
+
+    let differs   = |offset: usize| original.get(offset) != Some(&modified[offset]);
+    let mut patch = IPS_HEADER.to_vec();
+    let mut start = 0;
+
+    while start < modified.len() {
+        if !differs(start) {
+            start += 1;
+            continue;
+        }
+        if start == IPS_EOF_OFFSET {
+            start -= 1;
+        }
+
+        let mut end = start + 1;
+
+        while end < modified.len() && end - start < IPS_MAX_RECORD && differs(end) {
+            end += 1;
+        }
+
+        patch.extend_from_slice(&u24(start));
+        patch.extend_from_slice(&((end - start) as u16).to_be_bytes());
+        patch.extend_from_slice(&modified[start..end]);
+        start = end;
+    }
+
+    patch.extend_from_slice(IPS_EOF);
+    if modified.len() < original.len() {
+        patch.extend_from_slice(&u24(modified.len()));
+    }
+    Ok(patch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize, patch: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+
+            value >>= 7;
+            if value == 0 {
+                patch.push(byte | 0x80);
+                return;
+            }
+            patch.push(byte);
+            value -= 1;
+        }
+    }
+
+    #[test]
+    fn ips_round_trips_changes_growth_and_truncation() {
+        let original = vec![0u8; 0x100];
+        let mut modified = original.clone();
+
+        modified[0x10] = 1;
+        modified[0x11] = 2;
+        modified[0xff] = 3;
+        modified.extend_from_slice(&[4, 5]);
+
+        let patch = create_ips(&original, &modified).unwrap();
+        assert_eq!(apply(&original, &patch).unwrap(), modified);
+
+        let shorter = &original[..0x80];
+        let patch   = create_ips(&original, shorter).unwrap();
+        assert_eq!(apply(&original, &patch).unwrap(), shorter);
+    }
+
+    #[test]
+    fn ips_applies_run_length_records() {
+        let patch = b"PATCH\x00\x00\x02\x00\x00\x00\x03\xaaEOF";
+
+        assert_eq!(apply(&[0; 4], patch).unwrap(), [0, 0, 0xaa, 0xaa, 0xaa]);
+        assert!(apply(&[0; 4], b"PATCH\x00\x00\x02\x00").is_err());
+    }
+
+    #[test]
+    fn bps_applies_every_action_and_checks_crcs() {
+        let source = b"abcdef".to_vec();
+        let target = b"abcXYXYXdef".to_vec();
+        let mut patch = BPS_HEADER.to_vec();
+
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        varint((3 - 1) << 2, &mut patch);                // SourceRead "abc"
+        varint(((2 - 1) << 2) | 1, &mut patch);          // TargetRead "XY"
+        patch.extend_from_slice(b"XY");
+        varint(((3 - 1) << 2) | 3, &mut patch);          // TargetCopy "XYX"
+        varint(3 << 1, &mut patch);
+        varint(((3 - 1) << 2) | 2, &mut patch);          // SourceCopy "def"
+        varint(3 << 1, &mut patch);
+        patch.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch.extend_from_slice(&crc32(&target).to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+        assert!(apply(b"abcdeg", &patch).is_err());
+
+        let last = patch.len() - 1;
+        patch[last] ^= 1;
+        assert!(apply(&source, &patch).is_err());
+    }
+
+    // A patch with valid checksums around the given actions, so only the
+    // actions themselves can make it fail.
+    fn bps(source: &[u8], target_size: usize, actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_HEADER.to_vec();
+
+        varint(source.len(), &mut patch);
+        varint(target_size, &mut patch);
+        varint(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&0u32.to_le_bytes());
+        patch.extend_from_slice(&crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn bps_rejects_truncated_and_oversized_patches() {
+        let source = b"abcdef";
+        let mut actions = Vec::new();
+
+        // TargetRead of four bytes with only two present.
+        varint(((4 - 1) << 2) | 1, &mut actions);
+        actions.extend_from_slice(b"XY");
+        assert!(apply(source, &bps(source, 4, &actions)).is_err());
+
+        // A self-overlapping TargetCopy far longer than the target.
+        let mut actions = Vec::new();
+
+        varint(1, &mut actions);
+        actions.push(b'X');
+        varint(((1 << 40) << 2) | 3, &mut actions);
+        varint(0, &mut actions);
+        assert!(apply(source, &bps(source, 8, &actions)).is_err());
+
+        // SourceRead past the declared target size.
+        let mut actions = Vec::new();
+
+        varint((6 - 1) << 2, &mut actions);
+        assert!(apply(source, &bps(source, 4, &actions)).is_err());
+
+        // A huge declared target with nothing to fill it.
+        assert!(apply(source, &bps(source, usize::MAX >> 1, &[])).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+// A minimal PNG writer for 8-bit RGB images. The pixel data goes into
+// stored (uncompressed) deflate blocks, so no compression library is needed.
+
+use crate::checksum::crc32;
+
+const SIGNATURE:  [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+const MAX_STORED: usize   = 0xffff;
+
+pub fn encode(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let stride  = width as usize * 3;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+
+    for row in rgb.chunks(stride) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut header = Vec::new();
+
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib(&raw));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+
+    let crc = crc32(kind.iter().chain(data));
+
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks     = data.chunks(MAX_STORED).count();
+
+    for (index, block) in data.chunks(MAX_STORED).enumerate() {
+        let length = block.len() as u16;
+
+        stream.push(u8::from(index + 1 == blocks));
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    for byte in data {
+        a = (a + u32::from(*byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_checksum() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+    }
+}
@@ -1,6 +1,7 @@
 use std::ops::AddAssign;
 use std::ops::SubAssign;
 
+#[derive(Clone)]
 pub struct PointerRegister {
     register: u16,
 }
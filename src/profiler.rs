@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+
+use crate::call_stack::{CallFrame, CallKind};
+
+// One minute of frames at 60 Hz.
+const MAX_FRAMES: usize = 3600;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FrameProfile {
+    pub cycles:     u64,
+    pub interrupts: BTreeMap<u16, u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub address:          Option<u16>,
+    pub calls:            u64,
+    pub self_cycles:      u64,
+    pub inclusive_cycles: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub caller: Option<u16>,
+    pub callee: u16,
+    pub calls:  u64,
+    pub cycles: u64,
+}
+
+// Cycles are charged to the whole call stack that was current when they
+// ran. The stack is the emulator's CallStack, handed over whenever it
+// changes. Functions are named by entry address and the code running
+// outside any call is the root, "main".
+#[derive(Clone)]
+pub struct Profiler {
+    stack:     Vec<u16>,
+    interrupt: Option<u16>,
+    pending:   u64,
+    stacks:    HashMap<Vec<u16>, u64>,
+    calls:     HashMap<(Option<u16>, u16), u64>,
+    frame:     FrameProfile,
+    frames:    VecDeque<FrameProfile>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            stack:     Vec::new(),
+            interrupt: None,
+            pending:   0,
+            stacks:    HashMap::new(),
+            calls:     HashMap::new(),
+            frame:     FrameProfile::default(),
+            frames:    VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, cycles: u8) {
+        let cycles = u64::from(cycles);
+
+        self.pending      += cycles;
+        self.frame.cycles += cycles;
+
+        if let Some(vector) = self.interrupt {
+            *self.frame.interrupts.entry(vector).or_default() += cycles;
+        }
+    }
+
+    // Takes the call stack after an instruction that may have changed it.
+    // Cycles recorded so far belong to the stack before the change, and
+    // every frame past the part both stacks share is a new call.
+    pub fn follow(&mut self, frames: &[CallFrame]) {
+        let shared = self.stack.iter()
+            .zip(frames)
+            .take_while(|(address, frame)| **address == frame.target)
+            .count();
+
+        if shared == self.stack.len() && shared == frames.len() {
+            return;
+        }
+        self.flush();
+        self.stack.truncate(shared);
+
+        for frame in &frames[shared..] {
+            *self.calls.entry((self.stack.last().copied(), frame.target)).or_default() += 1;
+            self.stack.push(frame.target);
+        }
+
+        self.interrupt = frames.iter().rev()
+            .find(|frame| frame.kind == CallKind::Interrupt)
+            .map(|frame| frame.target);
+    }
+
+    pub fn reset(&mut self) {
+        self.follow(&[]);
+    }
+
+    pub fn end_frame(&mut self) {
+        if self.frames.len() == MAX_FRAMES {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(std::mem::take(&mut self.frame));
+    }
+
+    // The most recent completed frames, oldest first.
+    pub fn frames(&self) -> impl Iterator<Item = &FrameProfile> {
+        self.frames.iter()
+    }
+
+    fn flush(&mut self) {
+        if self.pending > 0 {
+            *self.stacks.entry(self.stack.clone()).or_default() += self.pending;
+            self.pending = 0;
+        }
+    }
+
+    fn samples(&self) -> Vec<(Vec<u16>, u64)> {
+        let mut stacks = self.stacks.clone();
+
+        if self.pending > 0 {
+            *stacks.entry(self.stack.clone()).or_default() += self.pending;
+        }
+        stacks.into_iter().collect()
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.samples().iter().map(|(_, cycles)| cycles).sum()
+    }
+
+    // Sorted by self cycles. A recursive function is only counted once per
+    // stack towards its inclusive cycles.
+    pub fn functions(&self) -> Vec<Function> {
+        fn entry(functions: &mut HashMap<Option<u16>, Function>, address: Option<u16>)
+                -> &mut Function {
+            functions.entry(address).or_insert(Function {
+                address,
+                calls:            0,
+                self_cycles:      0,
+                inclusive_cycles: 0,
+            })
+        }
+
+        let mut functions = HashMap::new();
+
+        for (stack, cycles) in self.samples() {
+            let mut seen = Vec::new();
+
+            for address in std::iter::once(None).chain(stack.iter().copied().map(Some)) {
+                if !seen.contains(&address) {
+                    seen.push(address);
+                    entry(&mut functions, address).inclusive_cycles += cycles;
+                }
+            }
+            entry(&mut functions, stack.last().copied()).self_cycles += cycles;
+        }
+        for ((_, callee), calls) in &self.calls {
+            entry(&mut functions, Some(*callee)).calls += calls;
+        }
+
+        let mut functions: Vec<Function> = functions.into_values().collect();
+
+        functions.sort_by_key(|function| (std::cmp::Reverse(function.self_cycles), function.address));
+        functions
+    }
+
+    // Sorted by the cycles spent below each edge.
+    pub fn call_graph(&self) -> Vec<Edge> {
+        let mut edges: HashMap<(Option<u16>, u16), Edge> = HashMap::new();
+
+        for (stack, cycles) in self.samples() {
+            let mut seen = Vec::new();
+            let callers  = std::iter::once(None).chain(stack.iter().copied().map(Some));
+
+            for (caller, callee) in callers.zip(stack.iter().copied()) {
+                if !seen.contains(&(caller, callee)) {
+                    seen.push((caller, callee));
+                    edges.entry((caller, callee))
+                        .or_insert(Edge { caller, callee, calls: 0, cycles: 0 })
+                        .cycles += cycles;
+                }
+            }
+        }
+        for ((caller, callee), calls) in &self.calls {
+            edges.entry((*caller, *callee))
+                .or_insert(Edge { caller: *caller, callee: *callee, calls: 0, cycles: 0 })
+                .calls += calls;
+        }
+
+        let mut edges: Vec<Edge> = edges.into_values().collect();
+
+        edges.sort_by_key(|edge| (std::cmp::Reverse(edge.cycles), edge.caller, edge.callee));
+        edges
+    }
+
+    // One line per distinct stack, as read by flamegraph.pl and inferno.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<String> = self.samples().iter()
+            .map(|(stack, cycles)| {
+                let mut line = String::from("main");
+
+                for address in stack {
+                    let _ = write!(line, ";{:04x}", address);
+                }
+                format!("{} {}", line, cycles)
+            })
+            .collect();
+
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+
+    // Average and worst share of a frame spent in the handler entered at
+    // the given vector, over the most recent completed frames.
+    pub fn interrupt_share(&self, vector: u16) -> (f64, f64) {
+        let shares: Vec<f64> = self.frames.iter()
+            .filter(|frame| frame.cycles > 0)
+            .map(|frame| *frame.interrupts.get(&vector).unwrap_or(&0) as f64 / frame.cycles as f64)
+            .collect();
+
+        if shares.is_empty() {
+            return (0.0, 0.0);
+        }
+        (shares.iter().sum::<f64>() / shares.len() as f64, shares.iter().cloned().fold(0.0, f64::max))
+    }
+
+    pub fn report(&self) -> String {
+        let total    = self.total_cycles().max(1) as f64;
+        let mut text = String::new();
+        let name     = |address: Option<u16>| match address {
+            Some(address) => format!("{:04x}", address),
+            None          => "main".to_string(),
+        };
+
+        let _ = writeln!(text, "function      calls        self       %   inclusive       %");
+        for function in self.functions() {
+            let _ = writeln!(text, "{: <8} {:>10} {:>11} {:>6.2}% {:>11} {:>6.2}%",
+                             name(function.address), function.calls,
+                             function.self_cycles, function.self_cycles as f64 * 100.0 / total,
+                             function.inclusive_cycles,
+                             function.inclusive_cycles as f64 * 100.0 / total);
+        }
+
+        let _ = writeln!(text, "\ncaller   callee        calls      cycles       %");
+        for edge in self.call_graph() {
+            let _ = writeln!(text, "{: <8} {: <8} {:>10} {:>11} {:>6.2}%",
+                             name(edge.caller), name(Some(edge.callee)), edge.calls,
+                             edge.cycles, edge.cycles as f64 * 100.0 / total);
+        }
+
+        let _ = writeln!(text, "\ninterrupt handler share of each frame over the last {} frames",
+                         self.frames.len());
+        for (number, vector) in [(1, 0x08), (2, 0x10)] {
+            let (average, worst) = self.interrupt_share(vector);
+
+            let _ = writeln!(text, "RST {}    average {:>6.2}%  worst {:>6.2}%",
+                             number, average * 100.0, worst * 100.0);
+        }
+        text
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(kind: CallKind, target: u16) -> CallFrame {
+        CallFrame { kind, from: 0, target, return_address: 0, return_sp: 0 }
+    }
+
+    #[test]
+    fn attributes_cycles_to_the_call_stack() {
+        let mut profiler = Profiler::new();
+        let function     = frame(CallKind::Call, 0x0100);
+        let interrupt    = frame(CallKind::Interrupt, 0x0008);
+
+        profiler.record(10);
+        profiler.follow(&[function]);
+        profiler.record(20);
+        profiler.follow(&[function, interrupt]);
+        profiler.record(5);
+        profiler.follow(&[function]);
+        profiler.record(7);
+        profiler.end_frame();
+        profiler.follow(&[]);
+        profiler.record(1);
+
+        assert_eq!(profiler.folded(), "main 11\nmain;0100 27\nmain;0100;0008 5\n");
+        assert_eq!(profiler.total_cycles(), 43);
+
+        let functions = profiler.functions();
+        let function  = functions.iter().find(|function| function.address == Some(0x0100)).unwrap();
+
+        assert_eq!((function.calls, function.self_cycles, function.inclusive_cycles), (1, 27, 32));
+        assert_eq!(profiler.interrupt_share(0x0008), (5.0 / 42.0, 5.0 / 42.0));
+    }
+
+    #[test]
+    fn frames_past_the_shared_part_of_the_stack_are_calls() {
+        let mut profiler = Profiler::new();
+        let outer        = frame(CallKind::Call, 0x0100);
+        let inner        = frame(CallKind::Rst, 0x0038);
+
+        profiler.follow(&[outer, inner]);
+        profiler.follow(&[outer]);
+        profiler.follow(&[outer, inner]);
+        profiler.follow(&[inner]);
+
+        let calls = |caller, callee| profiler.call_graph().iter()
+            .find(|edge| edge.caller == caller && edge.callee == callee)
+            .map_or(0, |edge| edge.calls);
+
+        assert_eq!(calls(None, 0x0100), 1);
+        assert_eq!(calls(Some(0x0100), 0x0038), 2);
+        assert_eq!(calls(None, 0x0038), 1);
+    }
+
+    #[test]
+    fn only_the_most_recent_frames_are_kept() {
+        let mut profiler = Profiler::new();
+
+        for cycles in 1..=MAX_FRAMES as u64 + 10 {
+            profiler.record(1);
+            profiler.frame.cycles = cycles;
+            profiler.end_frame();
+        }
+
+        assert_eq!(profiler.frames().count(), MAX_FRAMES);
+        assert_eq!(profiler.frames().next().unwrap().cycles, 11);
+    }
+}
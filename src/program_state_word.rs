@@ -1,5 +1,6 @@
 use crate::flag::Flag;
 
+#[derive(Clone)]
 pub struct ProgramStateWord {
     flags: u8,
     pub a: u8,
@@ -14,12 +15,20 @@ impl ProgramStateWord {
     }
 
     pub fn get(&self) -> u16 {
-        (u16::from(self.flags) << 8) | u16::from(self.a)
+        (u16::from(self.a) << 8) | u16::from(self.flags)
     }
 
     pub fn set(&mut self, value: u16) {
-        self.flags = (value >> 8)   as u8;
-        self.a     = (value & 0xff) as u8;
+        self.a     = (value >> 8)   as u8;
+        self.flags = (value & 0xff) as u8;
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn set_flags(&mut self, flags: u8) {
+        self.flags = flags;
     }
 
     pub fn get_carry(&self) -> u8 {
@@ -34,6 +43,10 @@ impl ProgramStateWord {
         self.set_flag(Flag::Carry, value);
     }
 
+    pub fn set_aux_carry(&mut self, value: u8) {
+        self.set_flag(Flag::AuxCarry, value);
+    }
+
     pub fn set_parity(&mut self, value: u8) {
         self.set_flag(Flag::Parity, value);
     }
@@ -46,15 +59,35 @@ impl ProgramStateWord {
         self.set_flag(Flag::Sign, value);
     }
 
+    pub fn set_overflow(&mut self, value: u8) {
+        self.set_flag(Flag::Overflow, value);
+    }
+
+    pub fn set_underflow(&mut self, value: u8) {
+        self.set_flag(Flag::Underflow, value);
+    }
+
+    // For results wider than a byte, where the flag is known directly.
+    pub fn set_zero_flag(&mut self, set: bool) {
+        self.assign_flag(Flag::Zero, set);
+    }
+
     fn set_flag(&mut self, flag: Flag, value: u8) {
         let should_set_flag = match flag {
-            Flag::Carry  => value != 0,
-            Flag::Parity => value.count_ones() & 1 == 0,
-            Flag::Zero   => value == 0,
-            Flag::Sign   => value & 0x80 == 0x80,
+            Flag::Carry     => value != 0,
+            Flag::Overflow  => value != 0,
+            Flag::Parity    => value.count_ones() & 1 == 0,
+            Flag::AuxCarry  => value != 0,
+            Flag::Underflow => value != 0,
+            Flag::Zero      => value == 0,
+            Flag::Sign      => value & 0x80 == 0x80,
         };
 
-        if should_set_flag {
+        self.assign_flag(flag, should_set_flag);
+    }
+
+    fn assign_flag(&mut self, flag: Flag, set: bool) {
+        if set {
             self.flags |=  u8::from(flag);
         } else {
             self.flags &= !u8::from(flag);
@@ -65,6 +98,10 @@ impl ProgramStateWord {
         self.is_flag_set(Flag::Carry)
     }
 
+    pub fn is_aux_carry_set(&self) -> bool {
+        self.is_flag_set(Flag::AuxCarry)
+    }
+
     pub fn is_parity_set(&self) -> bool {
         self.is_flag_set(Flag::Parity)
     }
@@ -77,6 +114,14 @@ impl ProgramStateWord {
         self.is_flag_set(Flag::Sign)
     }
 
+    pub fn is_overflow_set(&self) -> bool {
+        self.is_flag_set(Flag::Overflow)
+    }
+
+    pub fn is_underflow_set(&self) -> bool {
+        self.is_flag_set(Flag::Underflow)
+    }
+
     fn is_flag_set(&self, flag: Flag) -> bool {
         self.flags & u8::from(flag) != 0
     }
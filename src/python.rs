@@ -0,0 +1,157 @@
+use std::error::Error;
+
+use numpy::{PyArray1, PyArray2, PyArrayMethods};
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyType};
+
+use crate::emulator::Emulator;
+use crate::io_ports::Button;
+use crate::memory::Memory;
+use crate::video::{HEIGHT, WIDTH};
+
+fn runtime_error(error: Box<dyn Error>) -> PyErr {
+    PyRuntimeError::new_err(error.to_string())
+}
+
+// The start of `length` bytes at `address`, checked against the memory
+// before anything is read, written or allocated.
+fn span(memory: &Memory, address: u16, length: usize) -> PyResult<usize> {
+    let start = usize::from(address);
+
+    match start.checked_add(length) {
+        Some(end) if end <= memory.len() => Ok(start),
+        _ => Err(PyValueError::new_err(format!("{} bytes at {:04x} are outside memory", length, address))),
+    }
+}
+
+// A machine at one moment. States can be loaded any number of times, and
+// `bytes(state)` gives a copy that `State(data)` reads back, so they can be
+// written to disk or pickled.
+#[pyclass(frozen, module = "space_invaders")]
+struct State {
+    emulator: Emulator,
+}
+
+#[pymethods]
+impl State {
+    #[new]
+    fn new(data: &[u8]) -> PyResult<State> {
+        let mut emulator = Emulator::new();
+
+        emulator.load_state(data).map_err(|error| PyValueError::new_err(error.to_string()))?;
+        Ok(State { emulator })
+    }
+
+    fn __bytes__<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new(py, &self.emulator.save_state())
+    }
+
+    fn __reduce__<'py>(slf: &Bound<'py, Self>) -> (Bound<'py, PyType>, (Bound<'py, PyBytes>,)) {
+        (slf.get_type(), (slf.get().__bytes__(slf.py()),))
+    }
+}
+
+#[pyclass(module = "space_invaders")]
+struct Machine {
+    emulator: Emulator,
+}
+
+#[pymethods]
+impl Machine {
+    #[new]
+    fn new() -> Machine {
+        Machine {
+            emulator: Emulator::new(),
+        }
+    }
+
+    // Patches are IPS or BPS files applied in order.
+    #[pyo3(signature = (path, patches = Vec::new()))]
+    fn load_rom(&mut self, path: &str, patches: Vec<String>) -> PyResult<()> {
+        let patches: Vec<&str> = patches.iter().map(String::as_str).collect();
+
+        self.emulator.load_rom(path, &patches).map_err(runtime_error)
+    }
+
+    // The GIL is released while the frames run, so several machines can
+    // be stepped from Python threads at once.
+    #[pyo3(signature = (frames = 1))]
+    fn step_frame(&mut self, py: Python<'_>, frames: u32) -> PyResult<()> {
+        let emulator = &mut self.emulator;
+
+        py.detach(|| -> Result<(), String> {
+            for _ in 0..frames {
+                emulator.run_frame().map_err(|error| error.to_string())?;
+            }
+            Ok(())
+        }).map_err(PyRuntimeError::new_err)
+    }
+
+    fn set_button(&mut self, name: &str, pressed: bool) -> PyResult<()> {
+        let button = name.parse::<Button>().map_err(PyValueError::new_err)?;
+
+        self.emulator.set_button(button, pressed);
+        Ok(())
+    }
+
+    // The last complete frame, HEIGHT rows of WIDTH pixels, 1 where lit.
+    fn framebuffer<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyArray2<u8>>> {
+        PyArray1::from_slice(py, self.emulator.video().frame()).reshape([HEIGHT, WIDTH])
+    }
+
+    #[getter]
+    fn frames(&self) -> u64 {
+        self.emulator.video().frames()
+    }
+
+    #[getter]
+    fn cycles(&self) -> u64 {
+        self.emulator.cycles()
+    }
+
+    fn read8(&self, address: u16) -> PyResult<u8> {
+        span(self.emulator.memory(), address, 1)?;
+        Ok(self.emulator.memory().read8(address))
+    }
+
+    fn write8(&mut self, address: u16, value: u8) -> PyResult<()> {
+        span(self.emulator.memory(), address, 1)?;
+        self.emulator.memory_mut().write8(address, value);
+        Ok(())
+    }
+
+    fn read<'py>(&self, py: Python<'py>, address: u16, length: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let start    = span(self.emulator.memory(), address, length)?;
+        let mut data = vec![0u8; length];
+
+        self.emulator.memory().read(start, &mut data);
+        Ok(PyBytes::new(py, &data))
+    }
+
+    fn write(&mut self, address: u16, data: &[u8]) -> PyResult<()> {
+        let start = span(self.emulator.memory(), address, data.len())?;
+
+        self.emulator.memory_mut().write(start, data);
+        Ok(())
+    }
+
+    fn save_state(&self) -> State {
+        State {
+            emulator: self.emulator.clone(),
+        }
+    }
+
+    fn load_state(&mut self, state: &State) {
+        self.emulator = state.emulator.clone();
+    }
+}
+
+#[pymodule]
+fn space_invaders(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Machine>()?;
+    module.add_class::<State>()?;
+    module.add("WIDTH", WIDTH)?;
+    module.add("HEIGHT", HEIGHT)?;
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::memory::Memory;
+
+// Work RAM only. VRAM starts at 0x2400 and changes every frame.
+pub const WORK_RAM_START: u16 = 0x2000;
+pub const WORK_RAM_END:   u16 = 0x2400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    Equals(u8),
+}
+
+impl Filter {
+    fn matches(self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Changed       => current != previous,
+            Filter::Unchanged     => current == previous,
+            Filter::Increased     => current > previous,
+            Filter::Decreased     => current < previous,
+            Filter::Equals(value) => current == value,
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Filter, String> {
+        let mut words = text.split_whitespace();
+
+        match (words.next(), words.next(), words.next()) {
+            (Some("changed"),   None, None) => Ok(Filter::Changed),
+            (Some("unchanged"), None, None) => Ok(Filter::Unchanged),
+            (Some("increased"), None, None) => Ok(Filter::Increased),
+            (Some("decreased"), None, None) => Ok(Filter::Decreased),
+            (Some("equals" | "="), Some(value), None) => {
+                let value = match value.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None      => value.parse(),
+                };
+
+                value.map(Filter::Equals).map_err(|error| error.to_string())
+            }
+            _ => Err(format!("unknown filter {}", text)),
+        }
+    }
+}
+
+// Cheat Engine style narrowing: every filter compares the candidates
+// against the snapshot taken at the previous filter, then takes a new one.
+pub struct RamSearch {
+    snapshot:   Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    pub fn new(memory: &Memory) -> RamSearch {
+        let mut search = RamSearch {
+            snapshot:   Vec::new(),
+            candidates: Vec::new(),
+        };
+
+        search.reset(memory);
+        search
+    }
+
+    pub fn reset(&mut self, memory: &Memory) {
+        self.snapshot   = snapshot(memory);
+        self.candidates = (WORK_RAM_START..WORK_RAM_END).collect();
+    }
+
+    pub fn filter(&mut self, memory: &Memory, filter: Filter) -> usize {
+        let current = snapshot(memory);
+        let offset  = |address: u16| usize::from(address - WORK_RAM_START);
+
+        self.candidates.retain(|address| {
+            filter.matches(self.snapshot[offset(*address)], current[offset(*address)])
+        });
+        self.snapshot = current;
+        self.candidates.len()
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    // The value seen at the last snapshot.
+    pub fn value(&self, address: u16) -> u8 {
+        self.snapshot[usize::from(address - WORK_RAM_START)]
+    }
+
+    pub fn report(&self, limit: usize) -> String {
+        let mut report = String::new();
+
+        for address in self.candidates.iter().take(limit) {
+            let value = self.value(*address);
+
+            let _ = writeln!(report, "{:04x}  {:02x}  {:>3}", address, value, value);
+        }
+        if self.candidates.len() > limit {
+            let _ = writeln!(report, "... {} more", self.candidates.len() - limit);
+        }
+        let _ = writeln!(report, "{} candidates", self.candidates.len());
+        report
+    }
+}
+
+fn snapshot(memory: &Memory) -> Vec<u8> {
+    let mut data = vec![0u8; usize::from(WORK_RAM_END - WORK_RAM_START)];
+
+    memory.read(WORK_RAM_START.into(), &mut data);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_narrow_the_candidates() {
+        let mut memory = Memory::new();
+        let mut search = RamSearch::new(&memory);
+
+        memory.write8(0x2010, 5);
+        memory.write8(0x2020, 7);
+        assert_eq!(search.filter(&memory, Filter::Changed), 2);
+
+        memory.write8(0x2010, 4);
+        memory.write8(0x2020, 9);
+        assert_eq!(search.filter(&memory, Filter::Increased), 1);
+        assert_eq!(search.candidates(), [0x2020]);
+
+        assert_eq!(search.filter(&memory, Filter::Unchanged), 1);
+        assert_eq!(search.filter(&memory, "equals 0x09".parse().unwrap()), 1);
+        assert_eq!(search.filter(&memory, "= 8".parse().unwrap()), 0);
+        assert!("bigger".parse::<Filter>().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone)]
 pub struct RegisterPair {
     pub rh: u8,
     pub rl: u8,
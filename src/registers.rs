@@ -0,0 +1,14 @@
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Registers {
+    pub pc:   u16,
+    pub sp:   u16,
+    pub a:    u8,
+    pub f:    u8,
+    pub b:    u8,
+    pub c:    u8,
+    pub d:    u8,
+    pub e:    u8,
+    pub h:    u8,
+    pub l:    u8,
+    pub inte: bool,
+}
@@ -0,0 +1,136 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use crate::io_ports::Button;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Press(Button),
+    Release(Button),
+    // The cheats active from this frame on, in the cheat file format.
+    Cheats(String),
+}
+
+// A recorded session: the frame on which each button was pressed or
+// released, and the cheats that were active. The text form is one event
+// per line,
+//   press 120 coin
+//   release 128 coin
+//   cheats 0
+//   cheat 21ff=03 Infinite lives
+// where the cheat lines after `cheats` are the set active from that frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Replay {
+    events: Vec<(u64, Event)>,
+}
+
+impl Replay {
+    pub fn new() -> Replay {
+        Replay {
+            events: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, frame: u64, event: Event) {
+        self.events.push((frame, event));
+    }
+
+    pub fn events(&self) -> &[(u64, Event)] {
+        &self.events
+    }
+
+    pub fn events_at(&self, frame: u64) -> impl Iterator<Item = &Event> {
+        self.events.iter()
+            .filter(move |(at, _)| *at == frame)
+            .map(|(_, event)| event)
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, Box<dyn Error>> {
+        let mut replay = Replay::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let error = || format!("line {}: bad event {}", number + 1, line);
+
+            match line.split_once(' ') {
+                Some(("cheat", code)) => match replay.events.last_mut() {
+                    Some((_, Event::Cheats(cheats))) => {
+                        cheats.push_str(code);
+                        cheats.push('\n');
+                    }
+                    _ => return Err(format!("line {}: cheat outside a cheats block", number + 1).into()),
+                },
+                Some((kind, rest)) => {
+                    let mut words = rest.split_whitespace();
+                    let frame     = words.next().and_then(|word| word.parse().ok()).ok_or_else(error)?;
+                    let button    = words.next().map(str::parse::<Button>);
+
+                    let event = match (kind, button) {
+                        ("press",   Some(button)) => Event::Press(button?),
+                        ("release", Some(button)) => Event::Release(button?),
+                        ("cheats",  None)         => Event::Cheats(String::new()),
+                        _                         => return Err(error().into()),
+                    };
+                    replay.record(frame, event);
+                }
+                None => return Err(error().into()),
+            }
+        }
+        Ok(replay)
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, Box<dyn Error>> {
+        Replay::parse(&std::fs::read_to_string(path)?)
+            .map_err(|error| format!("{}: {}", path.display(), error).into())
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (frame, event) in &self.events {
+            match event {
+                Event::Press(button)   => writeln!(f, "press {} {}", frame, button)?,
+                Event::Release(button) => writeln!(f, "release {} {}", frame, button)?,
+                Event::Cheats(cheats)  => {
+                    writeln!(f, "cheats {}", frame)?;
+                    for line in cheats.lines() {
+                        writeln!(f, "cheat {}", line)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = "\
+cheats 0
+cheat 21ff=03 Infinite lives
+cheat 0002:00:ff
+press 120 coin
+release 128 coin
+cheats 300
+";
+
+    #[test]
+    fn round_trips_through_text() {
+        let replay = Replay::parse(FILE).unwrap();
+
+        assert_eq!(replay.events()[0], (0, Event::Cheats("21ff=03 Infinite lives\n0002:00:ff\n".to_string())));
+        assert_eq!(replay.events_at(120).collect::<Vec<_>>(), [&Event::Press(Button::Coin)]);
+        assert_eq!(replay.events_at(300).collect::<Vec<_>>(), [&Event::Cheats(String::new())]);
+        assert_eq!(replay.to_string(), FILE);
+    }
+
+    #[test]
+    fn rejects_bad_events() {
+        assert!(Replay::parse("press 1 nobody\n").is_err());
+        assert!(Replay::parse("press coin\n").is_err());
+        assert!(Replay::parse("cheat 21ff=03\n").is_err());
+        assert!(Replay::parse("cheats 0 1\n").is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use std::error::Error;
+
+use crate::snapshot::{Reader, Writer};
+
+// Index of a device registered with the emulator.
+pub type DeviceId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    MidScreen,
+    VBlank,
+    Device(DeviceId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timing {
+    clock_hz:   u64,
+    refresh_hz: u64,
+}
+
+impl Timing {
+    pub const SPACE_INVADERS: Timing = Timing { clock_hz: 2_000_000, refresh_hz: 60 };
+
+    pub fn new(clock_hz: u64, refresh_hz: u64) -> Result<Timing, Box<dyn Error>> {
+        if clock_hz == 0 || refresh_hz == 0 {
+            return Err("clock and refresh rates must be above zero".into());
+        }
+        Ok(Timing { clock_hz, refresh_hz })
+    }
+
+    pub fn clock_hz(&self) -> u64 {
+        self.clock_hz
+    }
+
+    pub fn refresh_hz(&self) -> u64 {
+        self.refresh_hz
+    }
+
+    pub fn frames(&self, frames: u64) -> u64 {
+        frames * self.clock_hz / self.refresh_hz
+    }
+
+    // Event times are derived from the frame number rather than accumulated,
+    // so a clock that does not divide evenly by the refresh rate never drifts.
+    pub fn mid_screen(&self, frame: u64) -> u64 {
+        (2 * frame + 1) * self.clock_hz / (2 * self.refresh_hz)
+    }
+
+    pub fn vblank(&self, frame: u64) -> u64 {
+        (frame + 1) * self.clock_hz / self.refresh_hz
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing::SPACE_INVADERS
+    }
+}
+
+// `next` caches the earliest deadline, so the check made after every
+// instruction is a single comparison.
+#[derive(Clone)]
+pub struct Scheduler {
+    cycles: u64,
+    next:   u64,
+    events: Vec<(u64, Event)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            cycles: 0,
+            next:   u64::MAX,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn schedule(&mut self, at: u64, event: Event) {
+        self.events.push((at, event));
+        self.next = self.next.min(at);
+    }
+
+    pub fn cancel(&mut self, event: Event) {
+        self.events.retain(|(_, pending)| *pending != event);
+        self.update_next();
+    }
+
+    pub fn advance(&mut self, cycles: u64) {
+        self.cycles += cycles;
+    }
+
+    pub fn pop_due(&mut self) -> Option<(u64, Event)> {
+        if self.cycles < self.next {
+            return None;
+        }
+
+        let (index, _) = self.events.iter()
+            .enumerate()
+            .min_by_key(|(_, (at, _))| *at)?;
+        let event = self.events.swap_remove(index);
+
+        self.update_next();
+        Some(event)
+    }
+
+    fn update_next(&mut self) {
+        self.next = self.events.iter().map(|(at, _)| *at).min().unwrap_or(u64::MAX);
+    }
+
+    // Device events are only restored for devices the machine has.
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.u64(self.cycles);
+        state.u64(self.events.len() as u64);
+
+        for (at, event) in &self.events {
+            state.u64(*at);
+
+            match event {
+                Event::MidScreen  => state.u8(0),
+                Event::VBlank     => state.u8(1),
+                Event::Device(id) => {
+                    state.u8(2);
+                    state.u64(*id as u64);
+                }
+            }
+        }
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader, devices: usize) -> Result<(), Box<dyn Error>> {
+        self.cycles = state.u64()?;
+        self.events.clear();
+
+        for _ in 0..state.u64()? {
+            let at    = state.u64()?;
+            let event = match state.u8()? {
+                0 => Event::MidScreen,
+                1 => Event::VBlank,
+                2 => Event::Device(usize::try_from(state.u64()?)?),
+                _ => return Err("saved state has an unknown event".into()),
+            };
+
+            if matches!(event, Event::Device(id) if id >= devices) {
+                return Err("saved state has an unknown device".into());
+            }
+            self.events.push((at, event));
+        }
+        self.update_next();
+        Ok(())
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn due(scheduler: &mut Scheduler) -> Vec<(u64, Event)> {
+        std::iter::from_fn(|| scheduler.pop_due()).collect()
+    }
+
+    #[test]
+    fn rates_must_be_above_zero() {
+        assert!(Timing::new(2_000_000, 0).is_err());
+        assert!(Timing::new(0, 60).is_err());
+        assert_eq!(Timing::new(2_000_000, 60).unwrap(), Timing::SPACE_INVADERS);
+    }
+
+    #[test]
+    fn pal_frames_are_40000_cycles() {
+        let timing = Timing::new(2_000_000, 50).unwrap();
+
+        assert_eq!(timing.mid_screen(0), 20_000);
+        assert_eq!(timing.vblank(0),     40_000);
+        assert_eq!(timing.mid_screen(1), 60_000);
+        assert_eq!(timing.vblank(1),     80_000);
+    }
+
+    #[test]
+    fn frames_do_not_drift() {
+        let timing = Timing::SPACE_INVADERS;
+
+        // 2 MHz / 60 Hz is 33333.3 cycles, so frames alternate between
+        // 33333 and 33334 cycles and every third one ends on a whole second.
+        assert_eq!(timing.vblank(0), 33_333);
+        assert_eq!(timing.vblank(1), 66_666);
+        assert_eq!(timing.vblank(2), 100_000);
+        assert_eq!(timing.vblank(60 * 3600 - 1), 3600 * 2_000_000);
+        assert_eq!(timing.frames(255), 8_500_000);
+    }
+
+    #[test]
+    fn events_come_due_in_cycle_order() {
+        let mut scheduler = Scheduler::new();
+
+        scheduler.schedule(300, Event::VBlank);
+        scheduler.schedule(100, Event::Device(0));
+        scheduler.schedule(200, Event::MidScreen);
+        scheduler.advance(250);
+
+        assert_eq!(due(&mut scheduler), [(100, Event::Device(0)), (200, Event::MidScreen)]);
+
+        scheduler.cancel(Event::VBlank);
+        scheduler.advance(100);
+
+        assert!(due(&mut scheduler).is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use std::cell::RefCell;
+use std::error::Error;
+use std::path::Path;
+use std::rc::Rc;
+
+use rhai::{Dynamic, Engine, EvalAltResult, FnPtr, FuncArgs, Map, NativeCallContext, INT};
+
+use crate::emulator::Emulator;
+use crate::io_ports::Button;
+use crate::memory::Memory;
+use crate::watch::WatchEvent;
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Default)]
+struct Hooks {
+    frame:    Vec<FnPtr>,
+    pc:       Vec<(u16, FnPtr)>,
+    read:     Vec<(u16, FnPtr)>,
+    write:    Vec<(u16, FnPtr)>,
+    port_in:  Vec<(u8, FnPtr)>,
+    port_out: Vec<(u8, FnPtr)>,
+}
+
+fn matching<T: PartialEq + Copy>(hooks: &[(T, FnPtr)], key: T) -> Vec<FnPtr> {
+    hooks.iter().filter(|(hook, _)| *hook == key).map(|(_, hook)| hook.clone()).collect()
+}
+
+fn call(context: &NativeCallContext, hooks: &[FnPtr], args: impl FuncArgs + Clone) -> ScriptResult<()> {
+    for hook in hooks {
+        let _ = hook.call_within_context::<Dynamic>(context, args.clone())?;
+    }
+    Ok(())
+}
+
+fn word(value: INT) -> ScriptResult<u16> {
+    u16::try_from(value).map_err(|_| format!("bad word {}", value).into())
+}
+
+// An address with `width` bytes of memory from it.
+fn address(memory: &Memory, value: INT, width: usize) -> ScriptResult<u16> {
+    match u16::try_from(value) {
+        Ok(address) if usize::from(address) + width <= memory.len() => Ok(address),
+        _ => Err(format!("bad address {}", value).into()),
+    }
+}
+
+fn byte(value: INT) -> ScriptResult<u8> {
+    u8::try_from(value).map_err(|_| format!("bad byte {}", value).into())
+}
+
+fn button(name: &str) -> ScriptResult<Button> {
+    name.parse::<Button>().map_err(Into::into)
+}
+
+// The emulator is shared with the registered functions for the length of
+// a script. Hooks are called between instructions, when it is not borrowed,
+// so they can use every function a script can.
+#[derive(Clone)]
+struct Machine {
+    emulator: Rc<RefCell<Emulator>>,
+    hooks:    Rc<RefCell<Hooks>>,
+}
+
+impl Machine {
+    fn step(&self, context: &NativeCallContext) -> ScriptResult<()> {
+        if !self.hooks.borrow().pc.is_empty() {
+            let pc    = self.emulator.borrow().registers().pc;
+            let hooks = matching(&self.hooks.borrow().pc, pc);
+
+            call(context, &hooks, (INT::from(pc),))?;
+        }
+
+        let frames = self.emulator.borrow().video().frames();
+
+        self.emulator.borrow_mut().step().map_err(|error| error.to_string())?;
+
+        let events = self.emulator.borrow_mut().take_watch_events();
+
+        for event in events {
+            let (hooks, key, value) = {
+                let hooks = self.hooks.borrow();
+
+                match event {
+                    WatchEvent::Read { address, value }  => (matching(&hooks.read, address), address, value),
+                    WatchEvent::Write { address, value } => (matching(&hooks.write, address), address, value),
+                    WatchEvent::PortIn { port, value }   => (matching(&hooks.port_in, port), port.into(), value),
+                    WatchEvent::PortOut { port, value }  => (matching(&hooks.port_out, port), port.into(), value),
+                }
+            };
+
+            call(context, &hooks, (INT::from(key), INT::from(value)))?;
+        }
+
+        let frame = self.emulator.borrow().video().frames();
+
+        if frame != frames {
+            let hooks = self.hooks.borrow().frame.clone();
+
+            call(context, &hooks, (frame as INT,))?;
+        }
+        Ok(())
+    }
+
+    fn frames(&self, context: &NativeCallContext, count: INT) -> ScriptResult<()> {
+        for _ in 0..count {
+            let frames = self.emulator.borrow().video().frames();
+
+            while self.emulator.borrow().video().frames() == frames {
+                self.step(context)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn registers(&self) -> Map {
+        let registers = self.emulator.borrow().registers();
+        let mut map   = Map::new();
+
+        for (name, value) in [
+            ("pc", registers.pc), ("sp", registers.sp),
+            ("a", registers.a.into()), ("f", registers.f.into()),
+            ("b", registers.b.into()), ("c", registers.c.into()),
+            ("d", registers.d.into()), ("e", registers.e.into()),
+            ("h", registers.h.into()), ("l", registers.l.into()),
+        ] {
+            map.insert(name.into(), Dynamic::from_int(value.into()));
+        }
+        map.insert("inte".into(), Dynamic::from_bool(registers.inte));
+        map
+    }
+
+    fn set_register(&self, name: &str, value: INT) -> ScriptResult<()> {
+        let mut emulator  = self.emulator.borrow_mut();
+        let mut registers = emulator.registers();
+
+        match name {
+            "pc" => registers.pc = word(value)?,
+            "sp" => registers.sp = word(value)?,
+            "a"  => registers.a  = byte(value)?,
+            "f"  => registers.f  = byte(value)?,
+            "b"  => registers.b  = byte(value)?,
+            "c"  => registers.c  = byte(value)?,
+            "d"  => registers.d  = byte(value)?,
+            "e"  => registers.e  = byte(value)?,
+            "h"  => registers.h  = byte(value)?,
+            "l"  => registers.l  = byte(value)?,
+            _    => return Err(format!("unknown register {}", name).into()),
+        }
+        emulator.set_registers(&registers);
+        Ok(())
+    }
+}
+
+fn engine(machine: &Machine) -> Engine {
+    let mut engine = Engine::new();
+
+    let m = machine.clone();
+    engine.register_fn("step", move |context: NativeCallContext| m.step(&context));
+    let m = machine.clone();
+    engine.register_fn("frame", move |context: NativeCallContext| m.frames(&context, 1));
+    let m = machine.clone();
+    engine.register_fn("run", move |context: NativeCallContext, frames: INT| m.frames(&context, frames));
+    let m = machine.clone();
+    engine.register_fn("frames", move || m.emulator.borrow().video().frames() as INT);
+    let m = machine.clone();
+    engine.register_fn("cycles", move || m.emulator.borrow().cycles() as INT);
+
+    let m = machine.clone();
+    engine.register_fn("press", move |name: &str| -> ScriptResult<()> {
+        m.emulator.borrow_mut().set_button(button(name)?, true);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("release", move |name: &str| -> ScriptResult<()> {
+        m.emulator.borrow_mut().set_button(button(name)?, false);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("hold", move |context: NativeCallContext, name: &str, frames: INT| -> ScriptResult<()> {
+        let button = button(name)?;
+
+        m.emulator.borrow_mut().set_button(button, true);
+        m.frames(&context, frames)?;
+        m.emulator.borrow_mut().set_button(button, false);
+        Ok(())
+    });
+
+    let m = machine.clone();
+    engine.register_fn("read8", move |at: INT| -> ScriptResult<INT> {
+        let emulator = m.emulator.borrow();
+        let memory   = emulator.memory();
+
+        Ok(memory.read8(address(memory, at, 1)?).into())
+    });
+    let m = machine.clone();
+    engine.register_fn("read16", move |at: INT| -> ScriptResult<INT> {
+        let emulator = m.emulator.borrow();
+        let memory   = emulator.memory();
+
+        Ok(memory.read16(address(memory, at, 2)?).into())
+    });
+    let m = machine.clone();
+    engine.register_fn("write8", move |at: INT, value: INT| -> ScriptResult<()> {
+        let mut emulator = m.emulator.borrow_mut();
+        let at           = address(emulator.memory(), at, 1)?;
+
+        emulator.memory_mut().write8(at, byte(value)?);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("write16", move |at: INT, value: INT| -> ScriptResult<()> {
+        let mut emulator = m.emulator.borrow_mut();
+        let at           = address(emulator.memory(), at, 2)?;
+
+        emulator.memory_mut().write16(at, word(value)?);
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("registers", move || m.registers());
+    let m = machine.clone();
+    engine.register_fn("set_register", move |name: &str, value: INT| m.set_register(name, value));
+
+    let m = machine.clone();
+    engine.register_fn("screenshot", move |path: &str| -> ScriptResult<()> {
+        std::fs::write(path, m.emulator.borrow().video().screenshot())
+            .map_err(|error| format!("{}: {}", path, error).into())
+    });
+    engine.register_fn("assert", |condition: bool, message: &str| -> ScriptResult<()> {
+        if condition { Ok(()) } else { Err(format!("assertion failed: {}", message).into()) }
+    });
+
+    let m = machine.clone();
+    engine.register_fn("on_frame", move |hook: FnPtr| m.hooks.borrow_mut().frame.push(hook));
+    let m = machine.clone();
+    engine.register_fn("on_pc", move |at: INT, hook: FnPtr| -> ScriptResult<()> {
+        let at = address(m.emulator.borrow().memory(), at, 1)?;
+
+        m.hooks.borrow_mut().pc.push((at, hook));
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("on_read", move |at: INT, hook: FnPtr| -> ScriptResult<()> {
+        let at = address(m.emulator.borrow().memory(), at, 1)?;
+
+        m.emulator.borrow_mut().watches_mut().watch_read(at);
+        m.hooks.borrow_mut().read.push((at, hook));
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("on_write", move |at: INT, hook: FnPtr| -> ScriptResult<()> {
+        let at = address(m.emulator.borrow().memory(), at, 1)?;
+
+        m.emulator.borrow_mut().watches_mut().watch_write(at);
+        m.hooks.borrow_mut().write.push((at, hook));
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("on_port_in", move |port: INT, hook: FnPtr| -> ScriptResult<()> {
+        let port = byte(port)?;
+
+        m.emulator.borrow_mut().watches_mut().watch_port_in(port);
+        m.hooks.borrow_mut().port_in.push((port, hook));
+        Ok(())
+    });
+    let m = machine.clone();
+    engine.register_fn("on_port_out", move |port: INT, hook: FnPtr| -> ScriptResult<()> {
+        let port = byte(port)?;
+
+        m.emulator.borrow_mut().watches_mut().watch_port_out(port);
+        m.hooks.borrow_mut().port_out.push((port, hook));
+        Ok(())
+    });
+
+    engine
+}
+
+// The emulator is moved into the script and handed back when it ends,
+// whether or not the script succeeded.
+pub fn run(emulator: &mut Emulator, source: &str) -> Result<(), Box<dyn Error>> {
+    let machine = Machine {
+        emulator: Rc::new(RefCell::new(std::mem::take(emulator))),
+        hooks:    Rc::new(RefCell::new(Hooks::default())),
+    };
+    let result = engine(&machine).run(source);
+
+    *emulator = machine.emulator.take();
+    result.map_err(|error| error.to_string().into())
+}
+
+pub fn run_file(emulator: &mut Emulator, path: &Path) -> Result<(), Box<dyn Error>> {
+    run(emulator, &std::fs::read_to_string(path)?)
+        .map_err(|error| format!("{}: {}", path.display(), error).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LXI SP,2400; MVI A,5; STA 2000; OUT 3; INR A; JMP 0005
+    const PROGRAM: [u8; 14] = [
+        0x31, 0x00, 0x24, 0x3e, 0x05, 0x32, 0x00, 0x20, 0xd3, 0x03, 0x3c, 0xc3, 0x05, 0x00,
+    ];
+
+    fn emulator() -> Emulator {
+        let mut emulator = Emulator::new();
+
+        emulator.memory_mut().write(0, &PROGRAM);
+        emulator
+    }
+
+    #[test]
+    fn hooks_see_accesses_and_frames() {
+        let mut emulator = emulator();
+
+        run(&mut emulator, r#"
+            let writes = [];
+            let ports  = 0;
+            let loops  = 0;
+            let frames = 0;
+
+            on_write(0x2000, |address, value| writes.push(value));
+            on_port_out(3, |port, value| ports += 1);
+            on_pc(0x0005, |pc| loops += 1);
+            on_frame(|frame| frames = frame);
+
+            for i in 0..8 { step(); }
+            assert(writes == [5, 6], "writes " + writes);
+            assert(ports == 2, "ports " + ports);
+            assert(loops == 2, "loops " + loops);
+            assert(registers().a == 6, "a");
+
+            run(2);
+            assert(frames == 2, "frames " + frames);
+            set_register("a", 0x42);
+        "#).unwrap();
+
+        assert_eq!(emulator.registers().a, 0x42);
+        assert_eq!(emulator.video().frames(), 2);
+    }
+
+    #[test]
+    fn failed_assertions_are_errors() {
+        let mut emulator = emulator();
+        let error = run(&mut emulator, r#"write8(0x2010, 7); assert(read8(0x2010) == 8, "byte");"#)
+            .unwrap_err();
+
+        assert!(error.to_string().contains("assertion failed: byte"));
+        assert_eq!(emulator.memory().read8(0x2010), 7);
+        assert!(run(&mut emulator, r#"press("nothing");"#).is_err());
+    }
+
+    #[test]
+    fn accesses_outside_memory_are_errors() {
+        let mut emulator = emulator();
+
+        for script in ["read8(0x6000);", "read16(0x4fff);", "write8(0x5000, 1);", "write16(0x4fff, 1);",
+                       "write16(0x2000, 0x10000);", "on_write(0x5000, |address, value| 0);"] {
+            assert!(run(&mut emulator, script).is_err(), "{}", script);
+        }
+        assert!(run(&mut emulator, "write16(0x4ffe, 0x1234); assert(read16(0x4ffe) == 0x1234, \"word\");").is_ok());
+    }
+}
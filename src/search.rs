@@ -0,0 +1,68 @@
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+use space_invaders::emulator::Emulator;
+use space_invaders::io_ports::Button;
+use space_invaders::ram_search::{Filter, RamSearch};
+
+const LIST_LIMIT: usize = 64;
+
+const HELP: &str = "\
+run [frames]        run the game, one frame by default
+press BUTTON        hold a button (coin, p1start, p1fire, p1left, ...)
+release BUTTON      let go of a button
+changed             keep addresses that changed since the last filter
+unchanged           keep addresses that did not change
+increased           keep addresses whose value went up
+decreased           keep addresses whose value went down
+equals N            keep addresses holding N (decimal or 0x hex)
+list                print the candidates
+reset               start over with all of work RAM
+quit";
+
+// Reads commands from stdin, so a search can also be scripted with a pipe.
+pub fn run(emulator: &mut Emulator) -> Result<(), Box<dyn Error>> {
+    let mut search = RamSearch::new(emulator.memory());
+    let mut stdout = std::io::stdout();
+
+    println!("{}", HELP);
+
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let mut words = line.split_whitespace();
+
+        match (words.next(), words.next()) {
+            (None, _) => {}
+            (Some("quit"), _) => break,
+            (Some("help"), _) => println!("{}", HELP),
+            (Some("run"), frames) => match frames.map_or(Ok(1), str::parse::<u64>) {
+                Ok(frames) => {
+                    for _ in 0..frames {
+                        emulator.run_frame()?;
+                    }
+                    println!("frame {}", emulator.video().frames());
+                }
+                Err(error) => println!("{}", error),
+            },
+            (Some(command @ ("press" | "release")), Some(button)) => match button.parse::<Button>() {
+                Ok(button) => emulator.set_button(button, command == "press"),
+                Err(error) => println!("{}", error),
+            },
+            (Some("list"), _) => print!("{}", search.report(LIST_LIMIT)),
+            (Some("reset"), _) => {
+                search.reset(emulator.memory());
+                println!("{} candidates", search.candidates().len());
+            }
+            _ => match line.parse::<Filter>() {
+                Ok(filter) => {
+                    search.filter(emulator.memory(), filter);
+                    print!("{}", search.report(LIST_LIMIT));
+                }
+                Err(error) => println!("{}", error),
+            },
+        }
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
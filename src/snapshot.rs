@@ -0,0 +1,133 @@
+use std::error::Error;
+
+// Saved states are a flat little-endian encoding. Each part of the machine
+// writes its own fields and reads them back in the same order.
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer {
+            bytes: Vec::new(),
+        }
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn bytes(&mut self, data: &[u8]) {
+        self.u64(data.len() as u64);
+        self.bytes.extend_from_slice(data);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes }
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if length > self.bytes.len() {
+            return Err("saved state is truncated".into());
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err("saved state is corrupt".into()),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Box<dyn Error>> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Box<dyn Error>> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into()?))
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], Box<dyn Error>> {
+        let length = usize::try_from(self.u64()?)?;
+
+        self.take(length)
+    }
+
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        if !self.bytes.is_empty() {
+            return Err("saved state has trailing data".into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_read_back_in_order() {
+        let mut writer = Writer::new();
+
+        writer.u8(0x12);
+        writer.bool(true);
+        writer.u16(0x3456);
+        writer.u64(u64::MAX - 1);
+        writer.bytes(b"abc");
+
+        let state      = writer.finish();
+        let mut reader = Reader::new(&state);
+
+        assert_eq!(reader.u8().unwrap(),    0x12);
+        assert!(reader.bool().unwrap());
+        assert_eq!(reader.u16().unwrap(),   0x3456);
+        assert_eq!(reader.u64().unwrap(),   u64::MAX - 1);
+        assert_eq!(reader.bytes().unwrap(), b"abc");
+        assert!(reader.finish().is_ok());
+    }
+
+    #[test]
+    fn short_corrupt_and_long_states_are_errors() {
+        assert!(Reader::new(&[0x12]).u16().is_err());
+        assert!(Reader::new(&[2]).bool().is_err());
+        assert!(Reader::new(&[0xff; 8]).bytes().is_err());
+        assert!(Reader::new(&[0]).finish().is_err());
+    }
+}
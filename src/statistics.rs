@@ -0,0 +1,29 @@
+#[derive(Clone)]
+pub struct Statistics {
+    pub instructions: u64,
+    pub cycles:       u64,
+    pub histogram:    [u64; 256],
+}
+
+impl Statistics {
+    pub fn new() -> Statistics {
+        Statistics {
+            instructions: 0,
+            cycles:       0,
+            histogram:    [0; 256],
+        }
+    }
+
+    pub fn record(&mut self, opcode: u8, cycles: u8) {
+        self.instructions += 1;
+        self.cycles       += u64::from(cycles);
+
+        self.histogram[usize::from(opcode)] += 1;
+    }
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,100 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::Path;
+
+// Labels carry no size, so an address is only named after a label that
+// starts close enough below it to plausibly be the same routine.
+const MAX_OFFSET: u16 = 0x100;
+
+// Address labels for backtraces. The file format is one "address name"
+// pair per line with the address in hex, and `;` starts a comment.
+#[derive(Clone)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols {
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Symbols, Box<dyn Error>> {
+        let mut symbols = Symbols::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split(';').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut fields = line.split_whitespace();
+            let (Some(address), Some(name), None) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(format!("line {}: expected an address and a name", number + 1).into());
+            };
+            let address = u16::from_str_radix(address.trim_start_matches("0x"), 16)
+                .map_err(|error| format!("line {}: {}", number + 1, error))?;
+
+            symbols.insert(address, name);
+        }
+        Ok(symbols)
+    }
+
+    // A missing file is not an error, there are just no labels.
+    pub fn load(path: &Path) -> Result<Symbols, Box<dyn Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Symbols::parse(&text).map_err(|error| format!("{}: {}", path.display(), error).into()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Symbols::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    pub fn insert(&mut self, address: u16, name: &str) {
+        self.labels.insert(address, name.to_string());
+    }
+
+    pub fn lookup(&self, name: &str) -> Option<u16> {
+        self.labels.iter().find(|(_, label)| *label == name).map(|(address, _)| *address)
+    }
+
+    // The nearest label at or below the address, as name+offset.
+    pub fn label(&self, address: u16) -> Option<String> {
+        match self.labels.range(..=address).next_back() {
+            Some((start, name)) if *start == address => Some(name.clone()),
+            Some((start, name)) if address - start < MAX_OFFSET => {
+                Some(format!("{}+{:#x}", name, address - start))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn name(&self, address: u16) -> String {
+        self.label(address).unwrap_or_else(|| format!("{:04x}", address))
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_resolve_to_the_nearest_label() {
+        let symbols = Symbols::parse("; vectors\n0000 reset\n0x0008 rst1  ; mid screen\n").unwrap();
+
+        assert_eq!(symbols.name(0x0000), "reset");
+        assert_eq!(symbols.name(0x0003), "reset+0x3");
+        assert_eq!(symbols.name(0x0010), "rst1+0x8");
+        assert_eq!(symbols.name(0x1000), "1000");
+        assert_eq!(symbols.lookup("rst1"), Some(0x0008));
+        assert!(Symbols::parse("0000\n").is_err());
+        assert!(Symbols::parse("zz reset\n").is_err());
+    }
+}
@@ -0,0 +1,355 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use space_invaders::cheats::Cheats;
+use space_invaders::emulator::Emulator;
+use space_invaders::high_score::HighScore;
+use space_invaders::io_ports::Button;
+use space_invaders::replay::{Event, Replay};
+use space_invaders::video::{Video, HEIGHT, WIDTH};
+
+const FRAME_TIME:  Duration = Duration::from_nanos(1_000_000_000 / 60);
+const HOLD_FRAMES: u64      = 8;
+
+const WHITE: (u8, u8, u8) = (0xff, 0xff, 0xff);
+const RED:   (u8, u8, u8) = (0xff, 0x30, 0x30);
+const GREEN: (u8, u8, u8) = (0x30, 0xff, 0x30);
+
+const BUTTONS: [Button; 6] = [
+    Button::Coin,
+    Button::P1Start,
+    Button::P2Start,
+    Button::P1Fire,
+    Button::P1Left,
+    Button::P1Right,
+];
+
+struct RawMode {
+    settings: String,
+}
+
+impl RawMode {
+    fn enable() -> Result<RawMode, Box<dyn Error>> {
+        let output = Command::new("stty")
+            .arg("-g")
+            .stdin(Stdio::inherit())
+            .output()?;
+
+        if !output.status.success() {
+            return Err("stdin is not a terminal".into());
+        }
+
+        let settings = String::from_utf8(output.stdout)?.trim().to_string();
+
+        Command::new("stty")
+            .args(["raw", "-echo"])
+            .stdin(Stdio::inherit())
+            .status()?;
+
+        print!("\x1b[?25l\x1b[2J");
+
+        Ok(RawMode { settings })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = std::io::stdout().flush();
+
+        let _ = Command::new("stty")
+            .arg(&self.settings)
+            .stdin(Stdio::inherit())
+            .status();
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Key {
+    Press(Button),
+    ToggleCheat(usize),
+    Quit,
+}
+
+// Decodes keys one byte at a time. Escape sequences are read to their
+// final byte before they are mapped, so the digits of a CSI sequence are
+// never taken for the 1 and 2 keys.
+#[derive(Default)]
+struct KeyDecoder {
+    state: Escape,
+}
+
+#[derive(Default)]
+enum Escape {
+    #[default]
+    None,
+    Start,
+    Csi(Vec<u8>),
+    Ss3,
+}
+
+impl KeyDecoder {
+    fn feed(&mut self, byte: u8) -> Option<Key> {
+        if byte == 0x1b {
+            self.state = Escape::Start;
+            return None;
+        }
+
+        match std::mem::take(&mut self.state) {
+            Escape::None => key(byte),
+            Escape::Start => match byte {
+                b'[' => { self.state = Escape::Csi(Vec::new()); None }
+                b'O' => { self.state = Escape::Ss3; None }
+                _    => key(byte),
+            },
+            Escape::Csi(mut parameters) => match byte {
+                0x20..=0x3f => {
+                    parameters.push(byte);
+                    self.state = Escape::Csi(parameters);
+                    None
+                }
+                0x40..=0x7e => csi(&parameters, byte),
+                _           => None,
+            },
+            Escape::Ss3 => match byte {
+                b'P'..=b'S' => Some(Key::ToggleCheat(usize::from(byte - b'P'))),
+                _           => None,
+            },
+        }
+    }
+}
+
+fn key(byte: u8) -> Option<Key> {
+    match byte {
+        b'c'       => Some(Key::Press(Button::Coin)),
+        b'1'       => Some(Key::Press(Button::P1Start)),
+        b'2'       => Some(Key::Press(Button::P2Start)),
+        b' '       => Some(Key::Press(Button::P1Fire)),
+        b'a'       => Some(Key::Press(Button::P1Left)),
+        b'd'       => Some(Key::Press(Button::P1Right)),
+        b'q' | 0x3 => Some(Key::Quit),
+        _          => None,
+    }
+}
+
+// Arrow keys with a modifier carry parameters and are ignored. Some
+// terminals send F1 to F4 as CSI 11~ to 14~ rather than SS3 P to S.
+fn csi(parameters: &[u8], byte: u8) -> Option<Key> {
+    match (parameters, byte) {
+        (b"", b'D')   => Some(Key::Press(Button::P1Left)),
+        (b"", b'C')   => Some(Key::Press(Button::P1Right)),
+        (b"11", b'~') => Some(Key::ToggleCheat(0)),
+        (b"12", b'~') => Some(Key::ToggleCheat(1)),
+        (b"13", b'~') => Some(Key::ToggleCheat(2)),
+        (b"14", b'~') => Some(Key::ToggleCheat(3)),
+        _             => None,
+    }
+}
+
+fn spawn_reader() -> Receiver<Key> {
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+        let mut stdin   = std::io::stdin();
+        let mut buffer  = [0u8; 16];
+        let mut decoder = KeyDecoder::default();
+
+        while let Ok(count @ 1..) = stdin.read(&mut buffer) {
+            for &byte in &buffer[..count] {
+                if let Some(key) = decoder.feed(byte) {
+                    if sender.send(key).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    });
+
+    receiver
+}
+
+fn overlay(x: usize, y: usize) -> (u8, u8, u8) {
+    match y {
+        32..=63                             => RED,
+        184..=239                           => GREEN,
+        240..=255 if (16..134).contains(&x) => GREEN,
+        _                                   => WHITE,
+    }
+}
+
+// Each braille character covers a 2x4 block of pixels, which fits the
+// 224x256 display into 112x64 cells.
+fn render(video: &Video, output: &mut String) {
+    const DOTS: [[u8; 2]; 4] = [
+        [0x01, 0x08],
+        [0x02, 0x10],
+        [0x04, 0x20],
+        [0x40, 0x80],
+    ];
+
+    let mut colour = None;
+
+    output.clear();
+    output.push_str("\x1b[H");
+
+    for row in (0..HEIGHT).step_by(4) {
+        for column in (0..WIDTH).step_by(2) {
+            let mut dots = 0;
+
+            for (dy, bits) in DOTS.iter().enumerate() {
+                for (dx, bit) in bits.iter().enumerate() {
+                    if video.pixel(column + dx, row + dy) {
+                        dots |= bit;
+                    }
+                }
+            }
+
+            let cell_colour = overlay(column, row);
+
+            if colour != Some(cell_colour) {
+                let (r, g, b) = cell_colour;
+
+                let _ = write!(output, "\x1b[38;2;{};{};{}m", r, g, b);
+                colour = Some(cell_colour);
+            }
+            output.push(char::from_u32(0x2800 + u32::from(dots)).unwrap());
+        }
+        output.push_str("\r\n");
+    }
+}
+
+// Plays from the keyboard, or from `playback` when given, and returns a
+// recording of the session. Only `q` is read while playing back.
+pub fn run(emulator: &mut Emulator, mut high_score: Option<&mut HighScore>,
+           playback: Option<&Replay>) -> Result<Replay, Box<dyn Error>> {
+    let _raw_mode = RawMode::enable()?;
+    let keys      = spawn_reader();
+
+    if let Some(high_score) = high_score.as_deref() {
+        high_score.watch(emulator.watches_mut());
+    }
+
+    let mut recording  = Replay::new();
+    let mut held_until = [0u64; BUTTONS.len()];
+    let mut held       = [false; BUTTONS.len()];
+    let mut status     = String::new();
+    let mut output     = String::new();
+    let mut stdout     = std::io::stdout();
+
+    if playback.is_none() {
+        recording.record(emulator.video().frames(), Event::Cheats(emulator.cheats().active()));
+    }
+
+    loop {
+        let start = Instant::now();
+        let frame = emulator.video().frames();
+
+        for key in keys.try_iter() {
+            match key {
+                Key::Quit => return Ok(recording),
+                _ if playback.is_some() => {}
+                Key::Press(button) => {
+                    let index = BUTTONS.iter().position(|b| *b == button).unwrap();
+
+                    held_until[index] = frame + HOLD_FRAMES;
+                }
+                // A patch that no longer matches the ROM must not end the
+                // game, so failures only go to the status line.
+                Key::ToggleCheat(index) => match emulator.toggle_cheat(index) {
+                    Ok(enabled) => {
+                        let state = if enabled { "on" } else { "off" };
+
+                        status = format!("cheat {} {}: {}", index + 1, state,
+                                         emulator.cheats().cheats()[index].description);
+                        recording.record(frame, Event::Cheats(emulator.cheats().active()));
+                    }
+                    Err(error) => status = error.to_string(),
+                },
+            }
+        }
+
+        match playback {
+            Some(playback) => {
+                for event in playback.events_at(frame) {
+                    match event {
+                        Event::Press(button)   => emulator.set_button(*button, true),
+                        Event::Release(button) => emulator.set_button(*button, false),
+                        Event::Cheats(cheats)  => emulator.set_cheats(Cheats::parse(cheats)?)?,
+                    }
+                    recording.record(frame, event.clone());
+                }
+            }
+            None => {
+                for ((button, until), held) in BUTTONS.iter().zip(held_until).zip(&mut held) {
+                    let pressed = frame < until;
+
+                    if pressed != *held {
+                        let event = if pressed { Event::Press(*button) } else { Event::Release(*button) };
+
+                        *held = pressed;
+                        recording.record(frame, event);
+                    }
+                    emulator.set_button(*button, pressed);
+                }
+            }
+        }
+
+        emulator.run_frame()?;
+
+        if let Some(high_score) = high_score.as_deref_mut() {
+            let events = emulator.take_watch_events();
+
+            high_score.update(&events, emulator.memory_mut());
+        }
+
+        render(emulator.video(), &mut output);
+        let _ = write!(output, "\x1b[0m{}\x1b[K", status);
+
+        stdout.write_all(output.as_bytes())?;
+        stdout.flush()?;
+
+        if let Some(remaining) = FRAME_TIME.checked_sub(start.elapsed()) {
+            thread::sleep(remaining);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::default();
+
+        bytes.iter().filter_map(|&byte| decoder.feed(byte)).collect()
+    }
+
+    #[test]
+    fn csi_parameters_are_not_keys() {
+        assert_eq!(decode(b"\x1b[1;5C\x1b[2~"), []);
+        assert_eq!(decode(b"\x1b[12~"), [Key::ToggleCheat(1)]);
+    }
+
+    #[test]
+    fn arrows_and_function_keys_are_decoded() {
+        assert_eq!(decode(b"\x1b[D\x1b[C\x1bOQ"), [
+            Key::Press(Button::P1Left),
+            Key::Press(Button::P1Right),
+            Key::ToggleCheat(1),
+        ]);
+    }
+
+    #[test]
+    fn keys_after_an_escape_sequence_are_read() {
+        assert_eq!(decode(b"\x1b[1;2D1\x1b[1\x1b[Cq"), [
+            Key::Press(Button::P1Start),
+            Key::Press(Button::P1Right),
+            Key::Quit,
+        ]);
+    }
+}
@@ -0,0 +1,108 @@
+use std::error::Error;
+
+use crate::memory::Memory;
+use crate::png;
+use crate::snapshot::{Reader, Writer};
+
+pub const WIDTH:  usize = 224;
+pub const HEIGHT: usize = 256;
+
+const VRAM_START:     usize = 0x2400;
+const BYTES_PER_LINE: usize = HEIGHT / 8;
+const MID_SCREEN:     usize = WIDTH / 2;
+
+#[derive(Clone)]
+pub struct Video {
+    frame:  Vec<u8>,
+    buffer: Vec<u8>,
+    frames: u64,
+}
+
+impl Video {
+    pub fn new() -> Video {
+        Video {
+            frame:  vec![0; WIDTH * HEIGHT],
+            buffer: vec![0; WIDTH * HEIGHT],
+            frames: 0,
+        }
+    }
+
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.frame[y * WIDTH + x] != 0
+    }
+
+    // The last complete frame as a PNG, white on black.
+    pub fn screenshot(&self) -> Vec<u8> {
+        let rgb: Vec<u8> = self.frame.iter().flat_map(|pixel| [pixel * 0xff; 3]).collect();
+
+        png::encode(WIDTH as u32, HEIGHT as u32, &rgb)
+    }
+
+    // The beam has drawn the top half of the screen when RST 1 fires and
+    // the bottom half when RST 2 fires, so each half is captured from VRAM
+    // as it was at that moment.
+    pub fn interrupt(&mut self, number: u8, memory: &Memory) {
+        match number {
+            1 => self.draw_lines(0..MID_SCREEN, memory),
+            2 => {
+                self.draw_lines(MID_SCREEN..WIDTH, memory);
+
+                std::mem::swap(&mut self.frame, &mut self.buffer);
+                self.frames += 1;
+            }
+            _ => {}
+        }
+    }
+
+    // VRAM holds the screen as columns from the bottom up, so it is read
+    // a half at a time and the frame is filled row by row to keep the
+    // writes sequential.
+    fn draw_lines(&mut self, lines: std::ops::Range<usize>, memory: &Memory) {
+        let mut vram = [0u8; MID_SCREEN * BYTES_PER_LINE];
+        let vram     = &mut vram[..lines.len() * BYTES_PER_LINE];
+
+        memory.read(VRAM_START + lines.start * BYTES_PER_LINE, vram);
+
+        for (y, row) in self.buffer.chunks_exact_mut(WIDTH).enumerate() {
+            let byte = (HEIGHT - 1 - y) / 8;
+            let bit  = (HEIGHT - 1 - y) % 8;
+
+            for (pixel, line) in row[lines.clone()].iter_mut().zip(vram.chunks_exact(BYTES_PER_LINE)) {
+                *pixel = (line[byte] >> bit) & 1;
+            }
+        }
+    }
+
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.bytes(&self.frame);
+        state.bytes(&self.buffer);
+        state.u64(self.frames);
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader) -> Result<(), Box<dyn Error>> {
+        let frame  = state.bytes()?;
+        let buffer = state.bytes()?;
+
+        if frame.len() != WIDTH * HEIGHT || buffer.len() != WIDTH * HEIGHT {
+            return Err("saved state has the wrong screen size".into());
+        }
+        self.frame.copy_from_slice(frame);
+        self.buffer.copy_from_slice(buffer);
+        self.frames = state.u64()?;
+        Ok(())
+    }
+}
+
+impl Default for Video {
+    fn default() -> Self {
+        Self::new()
+    }
+}
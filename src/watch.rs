@@ -0,0 +1,80 @@
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchEvent {
+    Read    { address: u16, value: u8 },
+    Write   { address: u16, value: u8 },
+    PortIn  { port: u8, value: u8 },
+    PortOut { port: u8, value: u8 },
+}
+
+// Accesses the CPU makes to watched addresses and ports are queued while
+// an instruction runs and collected afterwards with take_events.
+#[derive(Clone)]
+pub struct Watches {
+    reads:     Vec<bool>,
+    writes:    Vec<bool>,
+    ports_in:  [bool; 256],
+    ports_out: [bool; 256],
+    events:    Vec<WatchEvent>,
+}
+
+impl Watches {
+    pub fn new() -> Watches {
+        Watches {
+            reads:     vec![false; 0x10000],
+            writes:    vec![false; 0x10000],
+            ports_in:  [false; 256],
+            ports_out: [false; 256],
+            events:    Vec::new(),
+        }
+    }
+
+    pub fn watch_read(&mut self, address: u16) {
+        self.reads[usize::from(address)] = true;
+    }
+
+    pub fn watch_write(&mut self, address: u16) {
+        self.writes[usize::from(address)] = true;
+    }
+
+    pub fn watch_port_in(&mut self, port: u8) {
+        self.ports_in[usize::from(port)] = true;
+    }
+
+    pub fn watch_port_out(&mut self, port: u8) {
+        self.ports_out[usize::from(port)] = true;
+    }
+
+    pub fn take_events(&mut self) -> Vec<WatchEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub(crate) fn read(&mut self, address: u16, value: u8) {
+        if self.reads[usize::from(address)] {
+            self.events.push(WatchEvent::Read { address, value });
+        }
+    }
+
+    pub(crate) fn write(&mut self, address: u16, value: u8) {
+        if self.writes[usize::from(address)] {
+            self.events.push(WatchEvent::Write { address, value });
+        }
+    }
+
+    pub(crate) fn port_in(&mut self, port: u8, value: u8) {
+        if self.ports_in[usize::from(port)] {
+            self.events.push(WatchEvent::PortIn { port, value });
+        }
+    }
+
+    pub(crate) fn port_out(&mut self, port: u8, value: u8) {
+        if self.ports_out[usize::from(port)] {
+            self.events.push(WatchEvent::PortOut { port, value });
+        }
+    }
+}
+
+impl Default for Watches {
+    fn default() -> Self {
+        Self::new()
+    }
+}
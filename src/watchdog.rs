@@ -0,0 +1,60 @@
+use std::error::Error;
+
+use crate::scheduler::Timing;
+use crate::snapshot::{Reader, Writer};
+
+const TIMEOUT_FRAMES: u64 = 255;
+
+// The watchdog only remembers when it was last kicked. Its deadline event
+// checks that time when it comes due instead of being moved on every kick.
+#[derive(Clone)]
+pub struct Watchdog {
+    timeout:   u64,
+    kicked_at: u64,
+    enabled:   bool,
+}
+
+impl Watchdog {
+    pub fn new(timing: Timing) -> Watchdog {
+        Watchdog {
+            timeout:   timing.frames(TIMEOUT_FRAMES),
+            kicked_at: 0,
+            enabled:   true,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn kick(&mut self, cycles: u64) {
+        self.kicked_at = cycles;
+    }
+
+    pub fn deadline(&self) -> u64 {
+        self.kicked_at + self.timeout
+    }
+
+    pub(crate) fn save(&self, state: &mut Writer) {
+        state.u64(self.timeout);
+        state.u64(self.kicked_at);
+        state.bool(self.enabled);
+    }
+
+    pub(crate) fn load(&mut self, state: &mut Reader) -> Result<(), Box<dyn Error>> {
+        self.timeout   = state.u64()?;
+        self.kicked_at = state.u64()?;
+        self.enabled   = state.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Watchdog {
+    fn default() -> Self {
+        Self::new(Timing::default())
+    }
+}
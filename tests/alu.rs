@@ -0,0 +1,234 @@
+use proptest::prelude::*;
+
+use space_invaders::emulator::Emulator;
+use space_invaders::memory::Memory;
+use space_invaders::registers::Registers;
+
+const CARRY:     u8 = 1 << 0;
+const ALWAYS:    u8 = 1 << 1;
+const PARITY:    u8 = 1 << 2;
+const AUX_CARRY: u8 = 1 << 4;
+const ZERO:      u8 = 1 << 6;
+const SIGN:      u8 = 1 << 7;
+
+const M_ADDRESS: u16 = 0x2000;
+
+// Reference model: the flag byte the 8080 produces for a result.
+fn flags(result: u8, carry: bool, aux_carry: bool) -> u8 {
+    let mut flags = ALWAYS;
+
+    if carry                                  { flags |= CARRY; }
+    if result.count_ones().is_multiple_of(2)  { flags |= PARITY; }
+    if aux_carry                              { flags |= AUX_CARRY; }
+    if result == 0                            { flags |= ZERO; }
+    if result & 0x80 != 0                     { flags |= SIGN; }
+    flags
+}
+
+fn add(a: u8, b: u8, carry_in: bool) -> (u8, u8) {
+    let sum = u16::from(a) + u16::from(b) + u16::from(carry_in);
+    let ac  = (a & 0xf) + (b & 0xf) + u8::from(carry_in) > 0xf;
+
+    (sum as u8, flags(sum as u8, sum > 0xff, ac))
+}
+
+fn sub(a: u8, b: u8, borrow_in: bool) -> (u8, u8) {
+    let borrow = u16::from(a) < u16::from(b) + u16::from(borrow_in);
+    let result = a.wrapping_sub(b).wrapping_sub(u8::from(borrow_in));
+    let ac     = a & 0xf >= (b & 0xf) + u8::from(borrow_in);
+
+    (result, flags(result, borrow, ac))
+}
+
+// Returns the new accumulator and flags for ADD..CMP selected by `op`.
+fn alu(op: u8, a: u8, b: u8, carry: bool) -> (u8, u8) {
+    match op {
+        0 => add(a, b, false),
+        1 => add(a, b, carry),
+        2 => sub(a, b, false),
+        3 => sub(a, b, carry),
+        4 => (a & b, flags(a & b, false, (a | b) & 0x08 != 0)),
+        5 => (a ^ b, flags(a ^ b, false, false)),
+        6 => (a | b, flags(a | b, false, false)),
+        7 => (a, sub(a, b, false).1),
+        _ => unreachable!(),
+    }
+}
+
+fn set_register(registers: &mut Registers, reg: u8, value: u8) -> Vec<(u16, u8)> {
+    match reg {
+        0 => registers.b = value,
+        1 => registers.c = value,
+        2 => registers.d = value,
+        3 => registers.e = value,
+        4 => registers.h = value,
+        5 => registers.l = value,
+        6 => {
+            registers.h = (M_ADDRESS >> 8) as u8;
+            registers.l = M_ADDRESS as u8;
+            return vec![(M_ADDRESS, value)];
+        }
+        7 => registers.a = value,
+        _ => unreachable!(),
+    }
+    Vec::new()
+}
+
+fn get_register(emulator: &Emulator, reg: u8) -> u8 {
+    let registers = emulator.registers();
+
+    match reg {
+        0 => registers.b,
+        1 => registers.c,
+        2 => registers.d,
+        3 => registers.e,
+        4 => registers.h,
+        5 => registers.l,
+        6 => emulator.memory().read8(M_ADDRESS),
+        7 => registers.a,
+        _ => unreachable!(),
+    }
+}
+
+fn execute(program: &[u8], registers: &Registers, ram: &[(u16, u8)]) -> Emulator {
+    let mut emulator = Emulator::new();
+
+    *emulator.memory_mut() = Memory::with_size(0x10000);
+    emulator.memory_mut().write(0, program);
+
+    for (address, value) in ram {
+        emulator.memory_mut().write8(*address, *value);
+    }
+
+    emulator.set_registers(registers);
+    emulator.step().unwrap();
+    emulator
+}
+
+fn initial(a: u8, carry: bool, aux_carry: bool) -> Registers {
+    Registers {
+        a,
+        f: ALWAYS | if carry { CARRY } else { 0 } | if aux_carry { AUX_CARRY } else { 0 },
+        ..Registers::default()
+    }
+}
+
+proptest! {
+    #[test]
+    fn alu_register(op in 0..8u8, reg in 0..8u8, a: u8, value: u8, carry: bool) {
+        let mut registers = initial(a, carry, false);
+        let ram           = set_register(&mut registers, reg, value);
+
+        let emulator = execute(&[0x80 | (op << 3) | reg], &registers, &ram);
+        let (result, flags) = alu(op, registers.a, value, carry);
+
+        prop_assert_eq!(emulator.registers().a, result);
+        prop_assert_eq!(emulator.registers().f, flags);
+    }
+
+    #[test]
+    fn alu_immediate(op in 0..8u8, a: u8, data: u8, carry: bool) {
+        let emulator = execute(&[0xc6 | (op << 3), data], &initial(a, carry, false), &[]);
+        let (result, flags) = alu(op, a, data, carry);
+
+        prop_assert_eq!(emulator.registers().a, result);
+        prop_assert_eq!(emulator.registers().f, flags);
+    }
+
+    #[test]
+    fn inr_and_dcr(reg in 0..8u8, value: u8, carry: bool, decrement: bool) {
+        let mut registers = initial(0, carry, false);
+        let ram           = set_register(&mut registers, reg, value);
+        let opcode        = (reg << 3) | if decrement { 0x05 } else { 0x04 };
+
+        let emulator = execute(&[opcode], &registers, &ram);
+
+        let (result, flags) = if decrement { sub(value, 1, false) } else { add(value, 1, false) };
+        let flags           = flags & !CARRY | if carry { CARRY } else { 0 };
+
+        prop_assert_eq!(get_register(&emulator, reg), result);
+        prop_assert_eq!(emulator.registers().f, flags);
+    }
+
+    #[test]
+    fn dad(rp in 0..4u8, hl: u16, value: u16, carry: bool, aux_carry: bool) {
+        let mut registers = initial(0, carry, aux_carry);
+
+        [registers.h, registers.l] = hl.to_be_bytes();
+
+        match rp {
+            0 => [registers.b, registers.c] = value.to_be_bytes(),
+            1 => [registers.d, registers.e] = value.to_be_bytes(),
+            2 => {}
+            3 => registers.sp = value,
+            _ => unreachable!(),
+        }
+
+        let addend   = if rp == 2 { hl } else { value };
+        let emulator = execute(&[0x09 | (rp << 4)], &registers, &[]);
+        let sum      = u32::from(hl) + u32::from(addend);
+
+        let after = emulator.registers();
+
+        prop_assert_eq!(u16::from_be_bytes([after.h, after.l]), sum as u16);
+        prop_assert_eq!(after.f, registers.f & !CARRY | if sum > 0xffff { CARRY } else { 0 });
+    }
+
+    #[test]
+    fn rotates(op in 0..4u8, a: u8, carry: bool, aux_carry: bool) {
+        let registers = initial(a, carry, aux_carry);
+        let emulator  = execute(&[0x07 | (op << 3)], &registers, &[]);
+
+        let (result, carry_out) = match op {
+            0 => (a.rotate_left(1),                      a & 0x80 != 0),
+            1 => (a.rotate_right(1),                     a & 0x01 != 0),
+            2 => ((a << 1) | u8::from(carry),            a & 0x80 != 0),
+            3 => ((a >> 1) | (u8::from(carry) << 7),     a & 0x01 != 0),
+            _ => unreachable!(),
+        };
+
+        prop_assert_eq!(emulator.registers().a, result);
+        prop_assert_eq!(emulator.registers().f,
+                        registers.f & !CARRY | if carry_out { CARRY } else { 0 });
+    }
+
+    #[test]
+    fn daa(a: u8, carry: bool, aux_carry: bool) {
+        let emulator = execute(&[0x27], &initial(a, carry, aux_carry), &[]);
+
+        let mut correction = 0;
+        let mut carry_out  = carry;
+
+        if a & 0xf > 9 || aux_carry {
+            correction |= 0x06;
+        }
+        if a > 0x99 || carry {
+            correction |= 0x60;
+            carry_out = true;
+        }
+
+        let (result, flags) = add(a, correction, false);
+        let flags           = flags & !CARRY | if carry_out { CARRY } else { 0 };
+
+        prop_assert_eq!(emulator.registers().a, result);
+        prop_assert_eq!(emulator.registers().f, flags);
+    }
+
+    #[test]
+    fn complement_and_carry_flags(op in 0..3u8, a: u8, carry: bool, aux_carry: bool) {
+        // CMA, STC, CMC
+        let registers = initial(a, carry, aux_carry);
+        let emulator  = execute(&[[0x2f, 0x37, 0x3f][usize::from(op)]], &registers, &[]);
+
+        let (result, carry_out) = match op {
+            0 => (!a, carry),
+            1 => (a, true),
+            2 => (a, !carry),
+            _ => unreachable!(),
+        };
+
+        prop_assert_eq!(emulator.registers().a, result);
+        prop_assert_eq!(emulator.registers().f,
+                        registers.f & !CARRY | if carry_out { CARRY } else { 0 });
+    }
+}
@@ -0,0 +1,45 @@
+use space_invaders::batch::BatchEnv;
+use space_invaders::emulator::Emulator;
+use space_invaders::env::{Action, Env, EnvConfig};
+
+const ROM: &str = "./rom/space_invaders";
+
+fn assert_send<T: Send + Clone>() {}
+
+fn actions(step: usize, games: usize) -> Vec<Action> {
+    (0..games).map(|game| Action::ALL[(step / 8 + game) % Action::ALL.len()]).collect()
+}
+
+#[test]
+fn threads_do_not_change_the_results() {
+    assert_send::<Emulator>();
+
+    let config = EnvConfig { frame_skip: 1, sticky: 0.25, noop_max: 30, seed: 3, ..EnvConfig::default() };
+    let rom    = std::fs::read(ROM).unwrap();
+    let mut single   = BatchEnv::new(rom.clone(), config, 3).unwrap();
+    let mut threaded = BatchEnv::new(rom.clone(), config, 3).unwrap();
+    let mut env      = Env::new(rom, EnvConfig { seed: 4, ..config }).unwrap();
+
+    single.set_threads(1);
+    threaded.set_threads(3);
+    assert_eq!(single.reset().unwrap(), threaded.reset().unwrap());
+    env.reset().unwrap();
+
+    let size = 112 * 128;
+
+    for step in 0..200 {
+        let actions = actions(step, 3);
+
+        single.step(&actions).unwrap();
+        threaded.step(&actions).unwrap();
+
+        let result = env.step(actions[1]).unwrap();
+
+        assert_eq!(single.observations(), threaded.observations());
+        assert_eq!(single.rewards(), threaded.rewards());
+        assert_eq!(&threaded.observations()[size..2 * size], result.observation);
+        assert_eq!(threaded.rewards()[1], result.reward);
+    }
+    assert_eq!(threaded.observations().len(), 3 * size);
+    assert!(threaded.step(&[Action::Noop]).is_err());
+}
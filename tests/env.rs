@@ -0,0 +1,58 @@
+use space_invaders::env::{Action, Env, EnvConfig};
+
+const ROM: &str = "./rom/space_invaders";
+
+// Moves under the invaders, then taps fire.
+fn play(env: &mut Env, steps: usize) -> (i32, Vec<u8>) {
+    let mut reward = 0;
+    let mut last   = Vec::new();
+
+    for step in 0..steps {
+        let action = match step {
+            0..50              => Action::Right,
+            _ if step % 2 == 0 => Action::Fire,
+            _                  => Action::Noop,
+        };
+        let result = env.step(action).unwrap();
+
+        reward += result.reward;
+        last    = result.observation;
+        if result.done {
+            break;
+        }
+    }
+    (reward, last)
+}
+
+#[test]
+fn episodes_score_and_repeat_for_a_seed() {
+    let config = EnvConfig { sticky: 0.25, noop_max: 30, seed: 7, ..EnvConfig::default() };
+    let rom    = std::fs::read(ROM).unwrap();
+    let mut a  = Env::new(rom.clone(), config).unwrap();
+    let mut b  = Env::new(rom, config).unwrap();
+
+    assert_eq!(a.reset().unwrap().len(), 112 * 128);
+    b.reset().unwrap();
+
+    let (reward, observation) = play(&mut a, 300);
+
+    assert!(reward > 0);
+    assert_eq!(reward, a.score());
+    assert_eq!(play(&mut b, 300), (reward, observation));
+}
+
+#[test]
+fn rejects_bad_configs_actions_and_steps_before_reset() {
+    let rom = std::fs::read(ROM).unwrap();
+
+    assert!(Env::new(rom.clone(), EnvConfig { downsample: 3, ..EnvConfig::default() }).is_err());
+    assert!(Env::new(rom.clone(), EnvConfig { sticky: 1.5, ..EnvConfig::default() }).is_err());
+    assert!(Env::new(vec![0; 0x5001], EnvConfig::default()).is_err());
+    assert_eq!(Action::try_from(4), Ok(Action::RightFire));
+    assert!(Action::try_from(6).is_err());
+
+    let mut env = Env::new(rom, EnvConfig::default()).unwrap();
+
+    assert_eq!(env.observation_shape(), (112, 128));
+    assert!(env.step(Action::Noop).is_err());
+}
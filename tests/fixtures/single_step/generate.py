@@ -0,0 +1,346 @@
+#!/usr/bin/env python3
+"""Writes single step fixtures for a representative set of 8080 opcodes.
+
+The cases come from a reference model written from the 8080 data sheet,
+independent of the emulator, in the JSON layout of the single step test
+suites. Run it from this directory; the seed keeps the output stable.
+"""
+
+import json
+import random
+
+CASES = 10
+
+S, Z, AC, P, CY = 0x80, 0x40, 0x10, 0x04, 0x01
+
+OPCODES = [
+    0x02, 0x03, 0x04, 0x07, 0x09, 0x0b, 0x0d, 0x0f, 0x17, 0x1a, 0x1f, 0x22, 0x27, 0x29,
+    0x2a, 0x2f, 0x31, 0x32, 0x34, 0x35, 0x36, 0x37, 0x39, 0x3a, 0x3f, 0x41, 0x77, 0x7e,
+    0x80, 0x86, 0x8a, 0x8f, 0x92, 0x96, 0x9b, 0x9e, 0xa0, 0xa6, 0xad, 0xb4, 0xb8, 0xbe,
+    0xc0, 0xc1, 0xc2, 0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xcc, 0xcd, 0xce, 0xd3,
+    0xd6, 0xdb, 0xde, 0xe3, 0xe6, 0xe9, 0xeb, 0xee, 0xf1, 0xf3, 0xf5, 0xf6, 0xf9, 0xfe,
+    0xff,
+]
+
+LENGTHS = {0x01: 3, 0x11: 3, 0x21: 3, 0x31: 3, 0x22: 3, 0x2a: 3, 0x32: 3, 0x3a: 3,
+           0xc2: 3, 0xc3: 3, 0xc4: 3, 0xca: 3, 0xcc: 3, 0xcd: 3,
+           0x06: 2, 0x0e: 2, 0x16: 2, 0x1e: 2, 0x26: 2, 0x2e: 2, 0x36: 2, 0x3e: 2,
+           0xc6: 2, 0xce: 2, 0xd3: 2, 0xd6: 2, 0xdb: 2, 0xde: 2, 0xe6: 2, 0xee: 2,
+           0xf6: 2, 0xfe: 2}
+
+REGISTERS = "bcdehlma"
+
+
+def parity(value):
+    return bin(value).count("1") % 2 == 0
+
+
+class Cpu:
+    def __init__(self, state, memory):
+        self.__dict__.update({name: state[name] for name in "abcdefhl"})
+        self.pc, self.sp = state["pc"], state["sp"]
+        self.memory = memory
+        self.ports  = []
+        self.cycles = 0
+
+    def read(self, address):
+        return self.memory[address & 0xffff]
+
+    def write(self, address, value):
+        self.memory[address & 0xffff] = value & 0xff
+
+    def fetch(self):
+        value = self.read(self.pc)
+        self.pc = (self.pc + 1) & 0xffff
+        return value
+
+    def word(self):
+        low = self.fetch()
+        return low | self.fetch() << 8
+
+    def pair(self, name):
+        if name == "sp":
+            return self.sp
+        if name == "psw":
+            return self.a << 8 | self.f
+        return getattr(self, name[0]) << 8 | getattr(self, name[1])
+
+    def set_pair(self, name, value):
+        value &= 0xffff
+        if name == "sp":
+            self.sp = value
+        elif name == "psw":
+            self.a, self.f = value >> 8, value & 0xd7 | 0x02
+        else:
+            setattr(self, name[0], value >> 8)
+            setattr(self, name[1], value & 0xff)
+
+    def reg(self, code):
+        name = REGISTERS[code]
+        return self.read(self.pair("hl")) if name == "m" else getattr(self, name)
+
+    def set_reg(self, code, value):
+        name = REGISTERS[code]
+        if name == "m":
+            self.write(self.pair("hl"), value)
+        else:
+            setattr(self, name, value & 0xff)
+
+    def push(self, value):
+        self.sp = (self.sp - 2) & 0xffff
+        self.write(self.sp, value & 0xff)
+        self.write(self.sp + 1, value >> 8)
+
+    def pop(self):
+        value = self.read(self.sp) | self.read(self.sp + 1) << 8
+        self.sp = (self.sp + 2) & 0xffff
+        return value
+
+    def szp(self, value, carry, aux_carry):
+        self.f = 0x02
+        self.f |= S if value & 0x80 else 0
+        self.f |= Z if value == 0 else 0
+        self.f |= AC if aux_carry else 0
+        self.f |= P if parity(value) else 0
+        self.f |= CY if carry else 0
+
+    def alu(self, op, value):
+        a, carry = self.a, self.f & CY
+        if op in (0, 1):
+            carry_in = carry if op == 1 else 0
+            result = a + value + carry_in
+            self.szp(result & 0xff, result > 0xff, (a & 0xf) + (value & 0xf) + carry_in > 0xf)
+        elif op in (2, 3, 7):
+            borrow = carry if op == 3 else 0
+            result = a - value - borrow
+            self.szp(result & 0xff, result < 0, (a & 0xf) - (value & 0xf) - borrow >= 0)
+        elif op == 4:
+            result = a & value
+            self.szp(result, False, (a | value) & 0x08)
+        elif op == 5:
+            result = a ^ value
+            self.szp(result, False, False)
+        else:
+            result = a | value
+            self.szp(result, False, False)
+        if op != 7:
+            self.a = result & 0xff
+
+    def condition(self, code):
+        flag = [Z, CY, P, S][code >> 1]
+        return bool(self.f & flag) == bool(code & 1)
+
+    def step(self, port_input):
+        opcode = self.fetch()
+        cycles = 4
+
+        if opcode & 0xc0 == 0x40 and opcode != 0x76:
+            dst, src = opcode >> 3 & 7, opcode & 7
+            self.set_reg(dst, self.reg(src))
+            cycles = 7 if 6 in (dst, src) else 5
+        elif opcode & 0xc0 == 0x80:
+            self.alu(opcode >> 3 & 7, self.reg(opcode & 7))
+            cycles = 7 if opcode & 7 == 6 else 4
+        elif opcode & 0xc7 == 0xc6:
+            self.alu(opcode >> 3 & 7, self.fetch())
+            cycles = 7
+        elif opcode & 0xc7 == 0x04 or opcode & 0xc7 == 0x05:
+            code  = opcode >> 3 & 7
+            delta = 1 if opcode & 1 == 0 else -1
+            value = (self.reg(code) + delta) & 0xff
+            carry = self.f & CY
+            self.set_reg(code, value)
+            self.szp(value, carry, value & 0xf == 0 if delta == 1 else value & 0xf != 0xf)
+            cycles = 10 if code == 6 else 5
+        elif opcode & 0xc7 == 0x06:
+            code = opcode >> 3 & 7
+            self.set_reg(code, self.fetch())
+            cycles = 10 if code == 6 else 7
+        elif opcode & 0xcf == 0x01:
+            self.set_pair(["bc", "de", "hl", "sp"][opcode >> 4 & 3], self.word())
+            cycles = 10
+        elif opcode & 0xcf in (0x03, 0x0b):
+            name  = ["bc", "de", "hl", "sp"][opcode >> 4 & 3]
+            delta = 1 if opcode & 0x08 == 0 else -1
+            self.set_pair(name, self.pair(name) + delta)
+            cycles = 5
+        elif opcode & 0xcf == 0x09:
+            result = self.pair("hl") + self.pair(["bc", "de", "hl", "sp"][opcode >> 4 & 3])
+            self.set_pair("hl", result)
+            self.f = self.f & ~CY | (CY if result > 0xffff else 0)
+            cycles = 10
+        elif opcode in (0x02, 0x12):
+            self.write(self.pair(["bc", "de"][opcode >> 4]), self.a)
+            cycles = 7
+        elif opcode in (0x0a, 0x1a):
+            self.a = self.read(self.pair(["bc", "de"][opcode >> 4]))
+            cycles = 7
+        elif opcode in (0x07, 0x0f, 0x17, 0x1f):
+            a, carry = self.a, self.f & CY
+            if opcode == 0x07:
+                out, self.a = a >> 7, (a << 1 | a >> 7) & 0xff
+            elif opcode == 0x0f:
+                out, self.a = a & 1, (a >> 1 | a << 7) & 0xff
+            elif opcode == 0x17:
+                out, self.a = a >> 7, (a << 1 | carry) & 0xff
+            else:
+                out, self.a = a & 1, a >> 1 | carry << 7
+            self.f = self.f & ~CY | out
+        elif opcode == 0x22:
+            address = self.word()
+            self.write(address, self.l)
+            self.write(address + 1, self.h)
+            cycles = 16
+        elif opcode == 0x2a:
+            address = self.word()
+            self.l, self.h = self.read(address), self.read(address + 1)
+            cycles = 16
+        elif opcode == 0x32:
+            self.write(self.word(), self.a)
+            cycles = 13
+        elif opcode == 0x3a:
+            self.a = self.read(self.word())
+            cycles = 13
+        elif opcode == 0x27:
+            a, carry, correction = self.a, self.f & CY, 0
+            if self.f & AC or a & 0xf > 9:
+                correction |= 0x06
+            if carry or a >> 4 > 9 or (a >> 4 >= 9 and a & 0xf > 9):
+                correction |= 0x60
+                carry = 1
+            result = (a + correction) & 0xff
+            self.a = result
+            self.szp(result, carry, (a & 0xf) + (correction & 0xf) > 0xf)
+        elif opcode == 0x2f:
+            self.a ^= 0xff
+        elif opcode == 0x37:
+            self.f |= CY
+        elif opcode == 0x3f:
+            self.f ^= CY
+        elif opcode & 0xcf == 0xc5:
+            self.push(self.pair(["bc", "de", "hl", "psw"][opcode >> 4 & 3]))
+            cycles = 11
+        elif opcode & 0xcf == 0xc1:
+            self.set_pair(["bc", "de", "hl", "psw"][opcode >> 4 & 3], self.pop())
+            cycles = 10
+        elif opcode & 0xc7 == 0xc2 or opcode == 0xc3:
+            address = self.word()
+            if opcode == 0xc3 or self.condition(opcode >> 3 & 7):
+                self.pc = address
+            cycles = 10
+        elif opcode & 0xc7 == 0xc4 or opcode == 0xcd:
+            address = self.word()
+            cycles  = 11
+            if opcode == 0xcd or self.condition(opcode >> 3 & 7):
+                self.push(self.pc)
+                self.pc = address
+                cycles  = 17
+        elif opcode & 0xc7 == 0xc0:
+            cycles = 5
+            if self.condition(opcode >> 3 & 7):
+                self.pc = self.pop()
+                cycles  = 11
+        elif opcode == 0xc9:
+            self.pc = self.pop()
+            cycles  = 10
+        elif opcode & 0xc7 == 0xc7:
+            self.push(self.pc)
+            self.pc = opcode & 0x38
+            cycles  = 11
+        elif opcode == 0xe3:
+            value = self.pop()
+            self.push(self.pair("hl"))
+            self.set_pair("hl", value)
+            cycles = 18
+        elif opcode == 0xe9:
+            self.pc = self.pair("hl")
+            cycles  = 5
+        elif opcode == 0xeb:
+            self.d, self.e, self.h, self.l = self.h, self.l, self.d, self.e
+        elif opcode == 0xf9:
+            self.sp = self.pair("hl")
+            cycles  = 5
+        elif opcode == 0xdb:
+            port   = self.fetch()
+            self.a = port_input
+            self.ports.append([port, port_input, "r"])
+            cycles = 10
+        elif opcode == 0xd3:
+            port = self.fetch()
+            self.ports.append([port, self.a, "w"])
+            cycles = 10
+        elif opcode == 0xf3:
+            self.inte = 0
+        else:
+            raise ValueError(f"{opcode:02x} is not modelled")
+        self.cycles = cycles
+
+    def state(self, ram):
+        state = {name: getattr(self, name) for name in ("pc", "sp", "a", "b", "c", "d", "e", "f", "h", "l")}
+        state["ram"] = [[address, self.memory[address]] for address in ram]
+        return state
+
+
+def touched(opcode, state, pc):
+    """Addresses the instruction may read besides its own bytes."""
+    hl = state["h"] << 8 | state["l"]
+    sp = state["sp"]
+    addresses = [hl, sp, (sp + 1) & 0xffff, (state["b"] << 8 | state["c"]),
+                 (state["d"] << 8 | state["e"])]
+    return addresses + [(pc + offset) & 0xffff for offset in range(3)]
+
+
+def case(rng, opcode, index):
+    state = {name: rng.randrange(0x100) for name in "abcdehl"}
+    state["f"]  = rng.randrange(0x100) & 0xd7 | 0x02
+    state["pc"] = [0xfffe, 0xffff][index] if index < 2 else rng.randrange(0x10000)
+    state["sp"] = rng.randrange(0x10000)
+
+    memory = {}
+    length = LENGTHS.get(opcode, 1)
+
+    memory[state["pc"]] = opcode
+    for offset in range(1, length):
+        memory[(state["pc"] + offset) & 0xffff] = rng.randrange(0x100)
+
+    for address in touched(opcode, state, state["pc"]):
+        memory.setdefault(address, rng.randrange(0x100))
+
+    # Absolute operands point at memory the instruction may read.
+    if length == 3:
+        operand = memory[(state["pc"] + 1) & 0xffff] | memory[(state["pc"] + 2) & 0xffff] << 8
+        memory.setdefault(operand, rng.randrange(0x100))
+        memory.setdefault((operand + 1) & 0xffff, rng.randrange(0x100))
+
+    initial = dict(state)
+    initial["ram"] = sorted([address, value] for address, value in memory.items())
+
+    cpu        = Cpu(state, dict(memory))
+    port_input = rng.randrange(0x100)
+
+    cpu.inte = 0
+    cpu.step(port_input)
+
+    ram     = sorted(set(memory) | set(cpu.memory))
+    fixture = {
+        "name":    f"{opcode:02x} {index:04x}",
+        "initial": initial,
+        "final":   cpu.state(ram),
+        "cycles":  [[state["pc"], opcode, "r-m"]] + [[cpu.pc, None, "---"]] * (cpu.cycles - 1),
+    }
+    if cpu.ports:
+        fixture["ports"] = cpu.ports
+    return fixture
+
+
+def main():
+    rng = random.Random(8080)
+
+    for opcode in OPCODES:
+        with open(f"{opcode:02x}.json", "w") as file:
+            json.dump([case(rng, opcode, index) for index in range(CASES)], file)
+            file.write("\n")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,80 @@
+# Smoke tests for the Python module. Build it first with
+#
+#     maturin develop --release
+#
+# and run them from the repository root with `pytest tests/python`.
+
+import pickle
+
+import space_invaders
+
+ROM = "rom/space_invaders"
+
+
+def machine(frames):
+    machine = space_invaders.Machine()
+    machine.load_rom(ROM)
+    machine.step_frame(frames)
+    return machine
+
+
+def test_frames_advance():
+    assert machine(60).frames == 60
+
+
+def test_framebuffer_is_one_screen():
+    screen = machine(60).framebuffer()
+
+    assert screen.shape == (space_invaders.HEIGHT, space_invaders.WIDTH)
+    assert screen.dtype == "uint8"
+    assert screen.any()
+
+
+def test_load_state_rewinds_the_machine():
+    m     = machine(60)
+    state = m.save_state()
+    ram   = m.read(0x2000, 0x400)
+
+    m.step_frame(60)
+    m.load_state(state)
+
+    assert m.frames == 60
+    assert m.read(0x2000, 0x400) == ram
+
+
+def test_states_survive_bytes_and_pickle():
+    m     = machine(60)
+    state = m.save_state()
+    ram   = m.read(0x2000, 0x400)
+
+    for copy in [space_invaders.State(bytes(state)), pickle.loads(pickle.dumps(state))]:
+        other = space_invaders.Machine()
+        other.load_state(copy)
+
+        assert other.frames == 60
+        assert other.cycles == m.cycles
+        assert other.read(0x2000, 0x400) == ram
+
+
+def test_bad_state_bytes_are_rejected():
+    try:
+        space_invaders.State(b"not a state")
+    except ValueError:
+        return
+    raise AssertionError("expected ValueError")
+
+
+def test_out_of_range_memory_is_rejected():
+    machine = space_invaders.Machine()
+    for access in (lambda: machine.read8(0x6000),
+                   lambda: machine.write8(0x5000, 1),
+                   lambda: machine.read(0x4fff, 2),
+                   lambda: machine.read(0, 1 << 62),
+                   lambda: machine.write(0x4fff, b"ab")):
+        try:
+            access()
+        except ValueError:
+            continue
+        raise AssertionError("expected ValueError")
+    machine.write(0x4ffe, b"ab")
+    assert machine.read(0x4ffe, 2) == b"ab"
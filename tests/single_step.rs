@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use space_invaders::emulator::Emulator;
+use space_invaders::io_ports::IOPorts;
+use space_invaders::memory::Memory;
+use space_invaders::registers::Registers;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/single_step");
+const SUITE:    &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/8080");
+
+const MAX_REPORTED: usize = 10;
+
+fn registers(state: &Value) -> Registers {
+    let byte = |name: &str| state[name].as_u64().unwrap() as u8;
+    let word = |name: &str| state[name].as_u64().unwrap() as u16;
+
+    Registers {
+        pc:   word("pc"),
+        sp:   word("sp"),
+        a:    byte("a"),
+        f:    byte("f"),
+        b:    byte("b"),
+        c:    byte("c"),
+        d:    byte("d"),
+        e:    byte("e"),
+        h:    byte("h"),
+        l:    byte("l"),
+        inte: state["inte"].as_u64().unwrap_or(0) != 0,
+    }
+}
+
+fn ram(state: &Value) -> Vec<(u16, u8)> {
+    state["ram"].as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u16, entry[1].as_u64().unwrap() as u8))
+        .collect()
+}
+
+// Port accesses as (port, value, direction), direction being "r" or "w".
+fn ports(case: &Value) -> Vec<(u8, u8, String)> {
+    case["ports"].as_array()
+        .map_or(&[][..], Vec::as_slice)
+        .iter()
+        .map(|entry| (entry[0].as_u64().unwrap() as u8,
+                      entry[1].as_u64().unwrap() as u8,
+                      entry[2].as_str().unwrap().to_string()))
+        .collect()
+}
+
+// A bare CPU with 64K of RAM and ports that only latch values, so no case
+// depends on the board or on the one before it.
+fn machine() -> Emulator {
+    let mut emulator = Emulator::new();
+
+    *emulator.memory_mut()   = Memory::with_size(0x10000);
+    *emulator.io_ports_mut() = IOPorts::stub();
+    emulator.set_watchdog(false);
+    emulator
+}
+
+fn run_case(case: &Value) -> Vec<String> {
+    let initial = &case["initial"];
+    let fin     = &case["final"];
+    let mut emulator   = machine();
+    let mut mismatches = Vec::new();
+
+    emulator.set_registers(&registers(initial));
+
+    for (address, value) in ram(initial) {
+        emulator.memory_mut().write8(address, value);
+    }
+
+    for (port, value, _) in ports(case).into_iter().filter(|(_, _, direction)| direction == "r") {
+        emulator.io_ports_mut().write(port, value);
+    }
+
+    let cycles = match emulator.step() {
+        Ok(cycles) => cycles,
+        Err(error) => return vec![error.to_string()],
+    };
+
+    let expected = registers(fin);
+    let actual   = emulator.registers();
+
+    if actual != expected {
+        mismatches.push(format!("registers: expected {:x?}, got {:x?}", expected, actual));
+    }
+
+    for (address, value) in ram(fin) {
+        let got = emulator.memory().read8(address);
+
+        if got != value {
+            mismatches.push(format!("ram[{:04x}]: expected {:02x}, got {:02x}", address, value, got));
+        }
+    }
+
+    let expected_cycles = case["cycles"].as_array().unwrap().len();
+
+    if usize::from(cycles) != expected_cycles {
+        mismatches.push(format!("cycles: expected {}, got {}", expected_cycles, cycles));
+    }
+
+    for (port, value, _) in ports(case).into_iter().filter(|(_, _, direction)| direction == "w") {
+        let got = emulator.io_ports().read(port);
+
+        if got != value {
+            mismatches.push(format!("port {:02x}: expected {:02x}, got {:02x}", port, value, got));
+        }
+    }
+
+    mismatches
+}
+
+fn run_file(path: &Path) -> usize {
+    let cases: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    let mut failed   = 0;
+
+    for case in cases.as_array().unwrap() {
+        let mismatches = run_case(case);
+
+        if !mismatches.is_empty() {
+            if failed < MAX_REPORTED {
+                eprintln!("{}: {}", case["name"].as_str().unwrap_or("?"), mismatches.join("; "));
+            }
+            failed += 1;
+        }
+    }
+    failed
+}
+
+fn run_directory(directory: &str) -> Option<usize> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(directory).ok()?
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+
+    paths.sort();
+
+    let mut failed = 0;
+
+    for path in paths {
+        let file_failed = run_file(&path);
+
+        if file_failed != 0 {
+            eprintln!("{}: {} failing cases", path.display(), file_failed);
+        }
+        failed += file_failed;
+    }
+    Some(failed)
+}
+
+#[test]
+fn fixtures() {
+    assert_eq!(run_directory(FIXTURES), Some(0));
+}
+
+// Drop the per-opcode JSON files of a single step test suite into
+// tests/8080, or point SINGLE_STEP_TESTS at them, to run the full set.
+#[test]
+fn single_step_suite() {
+    let directory = std::env::var("SINGLE_STEP_TESTS").unwrap_or(SUITE.to_string());
+
+    match run_directory(&directory) {
+        Some(failed) => assert_eq!(failed, 0, "{} failing cases", failed),
+        None         => eprintln!("{} not found, skipping", directory),
+    }
+}